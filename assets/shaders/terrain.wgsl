@@ -0,0 +1,52 @@
+// Blends the detail texture of each biome across the tiles of a terrain chunk.
+// The biome texture has one texel per tile, rgb is the biome color and alpha the detail layer.
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+
+@group(2) @binding(100) var biome_texture: texture_2d<f32>;
+@group(2) @binding(101) var detail_texture: texture_2d_array<f32>;
+@group(2) @binding(102) var detail_sampler: sampler;
+
+// how many tiles one copy of a detail texture covers
+const DETAIL_SCALE: f32 = 4.0;
+
+fn tile_color(tile: vec2<i32>, world: vec2<f32>) -> vec3<f32> {
+    let max_tile = vec2<i32>(textureDimensions(biome_texture)) - 1;
+    let biome = textureLoad(biome_texture, clamp(tile, vec2<i32>(0), max_tile), 0);
+    let layer = i32(round(biome.a * 255.0));
+    let detail = textureSample(detail_texture, detail_sampler, world / DETAIL_SCALE, layer);
+    return biome.rgb * detail.rgb;
+}
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+#ifdef VERTEX_UVS_A
+    // texel centres sit on the vertices, so blend the four tiles around this point
+    let size = vec2<f32>(textureDimensions(biome_texture));
+    let tile = in.uv * size - 0.5;
+    let base = vec2<i32>(floor(tile));
+    let t = fract(tile);
+    let world = in.world_position.xz;
+    let color = mix(
+        mix(tile_color(base, world), tile_color(base + vec2<i32>(1, 0), world), t.x),
+        mix(tile_color(base + vec2<i32>(0, 1), world), tile_color(base + vec2<i32>(1, 1), world), t.x),
+        t.y,
+    );
+    pbr_input.material.base_color = vec4<f32>(color, 1.0) * pbr_input.material.base_color;
+#endif
+
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+}
@@ -0,0 +1,333 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<CollisionMap>()
+        .add_event::<EdgesChanged>()
+        .add_systems(Update, update_collision_map);
+}
+
+/// A side of a tile, north is +z and east is +x
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Side {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+    /// the step to the tile on the other side
+    pub fn offset(self) -> IVec3 {
+        match self {
+            Side::North => IVec3::Z,
+            Side::East => IVec3::X,
+            Side::South => IVec3::NEG_Z,
+            Side::West => IVec3::NEG_X,
+        }
+    }
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::North => Side::South,
+            Side::East => Side::West,
+            Side::South => Side::North,
+            Side::West => Side::East,
+        }
+    }
+}
+
+/// What a blocked edge stops
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    Movement,
+    Projectile,
+    Sight,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 3] = [Layer::Movement, Layer::Projectile, Layer::Sight];
+}
+
+/// The blocked sides of a tile for each layer, like the flags of a RuneScape collision map
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Edges(u16);
+
+impl Edges {
+    pub const NONE: Edges = Edges(0);
+
+    pub fn side(layer: Layer, side: Side) -> Edges {
+        Edges(1 << (layer as u16 * 4 + side as u16))
+    }
+
+    /// stops everything, for walls
+    pub fn wall(side: Side) -> Edges {
+        Layer::ALL.iter().fold(Edges::NONE, |edges, layer| {
+            edges | Edges::side(*layer, side)
+        })
+    }
+
+    /// stops movement but projectiles and sight go over, for fences and cliffs
+    pub fn fence(side: Side) -> Edges {
+        Edges::side(Layer::Movement, side)
+    }
+
+    pub fn blocks(self, layer: Layer, side: Side) -> bool {
+        self.0 & Edges::side(layer, side).0 != 0
+    }
+}
+
+impl BitOr for Edges {
+    type Output = Edges;
+
+    fn bitor(self, rhs: Edges) -> Edges {
+        Edges(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Edges {
+    fn bitor_assign(&mut self, rhs: Edges) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Edges of cells an entity blocks, they are in the [`CollisionMap`] while the entity exists.
+/// Only one side of an edge needs to be given, the tile on the other side is blocked to match.
+#[derive(Component, Clone, Default)]
+pub struct Blocker(pub Vec<(IVec3, Edges)>);
+
+/// The cells whose edges changed in one update of the [`CollisionMap`]
+#[derive(Event, Clone, Debug)]
+pub struct EdgesChanged(pub Vec<IVec3>);
+
+/// [`CollisionMap::blocked`] for anything that can give the edges of a cell
+pub fn step_blocked(from: IVec3, to: IVec3, layer: Layer, edges: impl Fn(IVec3) -> Edges) -> bool {
+    let x_side = match (to - from).x {
+        1 => Some(Side::East),
+        -1 => Some(Side::West),
+        _ => None,
+    };
+    let z_side = match (to - from).z {
+        1 => Some(Side::North),
+        -1 => Some(Side::South),
+        _ => None,
+    };
+    match (x_side, z_side) {
+        (Some(side), None) | (None, Some(side)) => edges(from).blocks(layer, side),
+        (Some(x_side), Some(z_side)) => {
+            let from_edges = edges(from);
+            from_edges.blocks(layer, x_side)
+                || from_edges.blocks(layer, z_side)
+                || edges(from + x_side.offset()).blocks(layer, z_side)
+                || edges(from + z_side.offset()).blocks(layer, x_side)
+        }
+        (None, None) => false,
+    }
+}
+
+/// Every blocked edge by cell
+#[derive(Resource, Default)]
+pub struct CollisionMap {
+    edges: HashMap<IVec3, Edges>,
+    /// the edges of each blocker with the matching sides of the cells across them
+    by_entity: HashMap<Entity, HashMap<IVec3, Edges>>,
+}
+
+impl CollisionMap {
+    pub fn edges(&self, cell: IVec3) -> Edges {
+        self.edges.get(&cell).copied().unwrap_or_default()
+    }
+
+    /// every cell with a blocked edge
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, Edges)> + '_ {
+        self.edges.iter().map(|(cell, edges)| (*cell, *edges))
+    }
+
+    /// whether a step to a neighbouring cell on the same plane crosses a blocked edge,
+    /// diagonal steps are blocked by any edge touching the corner they cut
+    pub fn blocked(&self, from: IVec3, to: IVec3, layer: Layer) -> bool {
+        step_blocked(from, to, layer, |cell| self.edges(cell))
+    }
+
+    /// whether a straight line between two cells on the same plane crosses a blocked edge,
+    /// walks the tiles under the line one step at a time
+    pub fn line_blocked(&self, from: IVec3, to: IVec3, layer: Layer) -> bool {
+        let delta = (to - from).as_vec3();
+        let steps = delta.x.abs().max(delta.z.abs()) as i32;
+        let mut current = from;
+        for step in 1..=steps {
+            let next = from + (delta * step as f32 / steps as f32).round().as_ivec3();
+            if self.blocked(current, next, layer) {
+                return true;
+            }
+            current = next;
+        }
+        false
+    }
+
+    /// whether one cell can see another
+    pub fn line_of_sight(&self, from: IVec3, to: IVec3) -> bool {
+        !self.line_blocked(from, to, Layer::Sight)
+    }
+
+    /// whether a projectile can fly between two cells
+    pub fn projectile_path(&self, from: IVec3, to: IVec3) -> bool {
+        !self.line_blocked(from, to, Layer::Projectile)
+    }
+
+    /// replaces the edges of a blocker and works out the cells it touches again,
+    /// the cells whose edges changed are added to `changed`
+    fn set(&mut self, entity: Entity, blocker: Option<&Blocker>, changed: &mut Vec<IVec3>) {
+        let old = match blocker {
+            Some(blocker) => self.by_entity.insert(entity, both_sides(&blocker.0)),
+            None => self.by_entity.remove(&entity),
+        }
+        .unwrap_or_default();
+        let empty = HashMap::new();
+        let new = self.by_entity.get(&entity).unwrap_or(&empty);
+        let touched = old
+            .iter()
+            .filter(|(cell, edges)| new.get(*cell) != Some(*edges))
+            .chain(
+                new.iter()
+                    .filter(|(cell, edges)| old.get(*cell) != Some(*edges)),
+            )
+            .map(|(cell, _)| *cell)
+            .collect::<HashSet<_>>();
+        for cell in touched {
+            let edges = self
+                .by_entity
+                .values()
+                .filter_map(|blocked| blocked.get(&cell))
+                .fold(Edges::NONE, |all, edges| all | *edges);
+            let old = if edges == Edges::NONE {
+                self.edges.remove(&cell)
+            } else {
+                self.edges.insert(cell, edges)
+            };
+            if old.unwrap_or_default() != edges {
+                changed.push(cell);
+            }
+        }
+    }
+}
+
+/// the edges of a blocker and the matching side of the cell across each one
+fn both_sides(blocked: &[(IVec3, Edges)]) -> HashMap<IVec3, Edges> {
+    let mut out = HashMap::<IVec3, Edges>::new();
+    for (cell, edges) in blocked {
+        *out.entry(*cell).or_default() |= *edges;
+        for layer in Layer::ALL {
+            for side in Side::ALL {
+                if edges.blocks(layer, side) {
+                    *out.entry(*cell + side.offset()).or_default() |=
+                        Edges::side(layer, side.opposite());
+                }
+            }
+        }
+    }
+    out
+}
+
+/// only the cells of blockers that changed are worked out again
+fn update_collision_map(
+    blockers: Query<(Entity, &Blocker), Changed<Blocker>>,
+    mut removed: RemovedComponents<Blocker>,
+    mut map: ResMut<CollisionMap>,
+    mut events: EventWriter<EdgesChanged>,
+) {
+    let mut changed = Vec::new();
+    for entity in removed.read() {
+        if map.by_entity.contains_key(&entity) {
+            map.set(entity, None, &mut changed);
+        }
+    }
+    for (entity, blocker) in &blockers {
+        map.set(entity, Some(blocker), &mut changed);
+    }
+    if !changed.is_empty() {
+        events.send(EdgesChanged(changed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a map with the edges of one blocker and the cells whose edges changed
+    fn map_with(blocked: Vec<(IVec3, Edges)>) -> (CollisionMap, Vec<IVec3>) {
+        let mut map = CollisionMap::default();
+        let mut changed = Vec::new();
+        map.set(Entity::from_raw(1), Some(&Blocker(blocked)), &mut changed);
+        (map, changed)
+    }
+
+    #[test]
+    fn edges_block_both_sides() {
+        let (map, changed) = map_with(vec![(IVec3::ZERO, Edges::fence(Side::East))]);
+        assert!(map.blocked(IVec3::ZERO, IVec3::X, Layer::Movement));
+        assert!(map.blocked(IVec3::X, IVec3::ZERO, Layer::Movement));
+        assert!(!map.blocked(IVec3::ZERO, IVec3::Z, Layer::Movement));
+        assert!(map.edges(IVec3::X).blocks(Layer::Movement, Side::West));
+        let mut changed = changed;
+        changed.sort_by_key(|cell| cell.x);
+        assert_eq!(changed, vec![IVec3::ZERO, IVec3::X]);
+    }
+
+    #[test]
+    fn removing_a_blocker_clears_its_cells() {
+        let (mut map, _) = map_with(vec![(IVec3::ZERO, Edges::wall(Side::North))]);
+        let mut changed = Vec::new();
+        map.set(Entity::from_raw(1), None, &mut changed);
+        assert_eq!(map.iter().count(), 0);
+        assert_eq!(changed.len(), 2);
+    }
+
+    #[test]
+    fn diagonals_cant_cut_blocked_corners() {
+        // from (0,0) to (1,1), every edge that touches the corner between them
+        for (cell, side) in [
+            (IVec3::ZERO, Side::East),
+            (IVec3::ZERO, Side::North),
+            (IVec3::X, Side::North),
+            (IVec3::Z, Side::East),
+        ] {
+            let (map, _) = map_with(vec![(cell, Edges::fence(side))]);
+            assert!(
+                map.blocked(IVec3::ZERO, IVec3::new(1, 0, 1), Layer::Movement),
+                "{side:?} of {cell} should block the corner",
+            );
+        }
+        // an edge on the far side of the target doesn't touch the corner
+        let (map, _) = map_with(vec![(IVec3::new(1, 0, 1), Edges::fence(Side::North))]);
+        assert!(!map.blocked(IVec3::ZERO, IVec3::new(1, 0, 1), Layer::Movement));
+    }
+
+    #[test]
+    fn fences_only_stop_movement() {
+        let (map, _) = map_with(vec![(IVec3::new(2, 0, 0), Edges::fence(Side::East))]);
+        let (from, to) = (IVec3::ZERO, IVec3::new(5, 0, 0));
+        assert!(map.line_blocked(from, to, Layer::Movement));
+        assert!(map.line_of_sight(from, to));
+        assert!(map.projectile_path(from, to));
+    }
+
+    #[test]
+    fn walls_stop_everything() {
+        let (map, _) = map_with(vec![(IVec3::new(2, 0, 1), Edges::wall(Side::East))]);
+        let (from, to) = (IVec3::ZERO, IVec3::new(5, 0, 2));
+        assert!(map.line_blocked(from, to, Layer::Movement));
+        assert!(!map.line_of_sight(from, to));
+        assert!(!map.projectile_path(from, to));
+        // a line that passes beside the wall isn't stopped
+        let beside = IVec3::new(5, 0, 5);
+        assert!(map.line_of_sight(IVec3::new(0, 0, 5), beside));
+        assert!(map.projectile_path(IVec3::new(0, 0, 5), beside));
+    }
+}
@@ -6,6 +6,8 @@ use bevy::{
     window::{CursorGrabMode, PrimaryWindow},
 };
 
+use crate::terrain::Terrain;
+
 #[derive(Component)]
 pub struct FlyCam;
 
@@ -13,7 +15,10 @@ impl Plugin for FlyCam {
     fn build(&self, app: &mut App) {
         app.init_resource::<FlySettings>()
             .add_systems(Update, cursor_toggle)
-            .add_systems(Update, (player_look, player_move))
+            .add_systems(
+                Update,
+                (player_look, (player_move, keep_above_ground).chain()),
+            )
             .add_systems(Startup, cursor_grab);
     }
 }
@@ -61,6 +66,22 @@ fn player_move(
     }
 }
 
+/// stops the camera flying into the ground
+fn keep_above_ground(
+    mut camera: Query<&mut Transform, With<FlyCam>>,
+    terrain: Query<&Terrain>,
+    settings: Res<FlySettings>,
+) {
+    let Ok(terrain) = terrain.get_single() else {
+        return;
+    };
+    for mut camera in &mut camera {
+        if let Some(ground) = terrain.hight_at(camera.translation.xz()) {
+            camera.translation.y = camera.translation.y.max(ground + settings.ground_clearance);
+        }
+    }
+}
+
 fn player_look(
     settings: Res<FlySettings>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
@@ -131,6 +152,8 @@ pub struct FlySettings {
     pub toggle_grab_cursor: KeyCode,
     pub mouse_sensitivity: f32,
     pub speed: f32,
+    /// how close to the ground the camera can get
+    pub ground_clearance: f32,
 }
 
 impl Default for FlySettings {
@@ -139,6 +162,7 @@ impl Default for FlySettings {
             toggle_grab_cursor: KeyCode::Escape,
             mouse_sensitivity: 0.00012,
             speed: 10.,
+            ground_clearance: 1.,
         }
     }
 }
@@ -2,41 +2,57 @@ use core::f32;
 
 use animations::Animation;
 use bevy::{ecs::system::SystemId, prelude::*, utils::HashMap};
-use path_finding::MoveCost;
+use path_finding::Goal;
 use rand::{seq::SliceRandom, Rng};
-use terrain::Terrain;
+use world_seed::{EntityRng, Stream, WorldSeed};
 
 mod animations;
+mod collision;
 mod fly_cam;
 mod path_finding;
+mod regions;
 mod terrain;
+mod world_seed;
 
 fn main() {
     let mut app = App::new();
     app.add_plugins((DefaultPlugins, MeshPickingPlugin, fly_cam::FlyCam))
         .init_resource::<CellAssets>()
         .init_resource::<CellIdToEntity>()
-        .add_systems(Startup, (spawn_camera, spawn_map, spawn_character))
+        .add_systems(Startup, (spawn_camera, spawn_character))
         .add_systems(
             Update,
             (
-                ray_casting,
-                move_entity,
+                (
+                    ray_casting.run_if(not(terrain::editing)),
+                    move_entity,
+                    build_path,
+                    apply_path,
+                    run_player_action,
+                )
+                    .run_if(in_state(terrain::TerrainState::Ready)),
                 update_cells,
                 add_root,
-                build_path,
-                run_player_action,
             ),
         )
         .add_plugins((
             animations::plugin,
+            collision::plugin,
             path_finding::plugin,
+            regions::plugin,
             terrain::plugin,
             ui::plugin,
+            world_seed::plugin,
         ));
+    if let Some(map) = terrain::ImportMap::from_args() {
+        app.insert_resource(map);
+    }
     #[cfg(debug_assertions)]
-    app.add_systems(FixedUpdate, random_move)
-        .insert_resource(Time::<Fixed>::from_hz(1.));
+    app.add_systems(
+        FixedUpdate,
+        random_move.run_if(in_state(terrain::TerrainState::Ready)),
+    )
+    .insert_resource(Time::<Fixed>::from_hz(1.));
     // app.add_systems(Update, (color_target, color_path, clear_color, random_move));
     // .add_plugins(Picki);
     app.run();
@@ -123,32 +139,34 @@ impl CellIdToEntity {
 }
 
 fn update_cells(
-    cells: Query<(Entity, &Transform), Added<Cell>>,
+    cells: Query<(Entity, &Transform, Option<&terrain::Plane>), Added<Cell>>,
     mut cell_map: ResMut<CellIdToEntity>,
     mut despawnd: RemovedComponents<Cell>,
 ) {
-    for (entity, cell) in &cells {
-        let mut id = cell.translation;
-        id.y = 0.;
-        if cell_map
-            .id_to_entity
-            .insert(id.round().as_ivec3(), entity)
-            .is_some()
-        {
-            warn!("Cell({}) is duplicated", id.round().as_ivec3());
-        };
-    }
+    // removals first so a chunk that reloads in the same frame keeps its new cells
     for entity in despawnd.read() {
         if let Some(id) = cell_map.entity_to_id.remove(&entity) {
-            cell_map.id_to_entity.remove(&id);
+            if cell_map.id_to_entity.get(&id) == Some(&entity) {
+                cell_map.id_to_entity.remove(&id);
+            }
         }
     }
+    for (entity, cell, plane) in &cells {
+        let mut id = cell.translation.round().as_ivec3();
+        id.y = plane.map_or(0, |plane| plane.0);
+        if let Some(old) = cell_map.id_to_entity.insert(id, entity) {
+            warn!("Cell({}) is duplicated", id);
+            cell_map.entity_to_id.remove(&old);
+        };
+        cell_map.entity_to_id.insert(entity, id);
+    }
 }
 
 fn spawn_camera(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn((
         DirectionalLight::default(),
-        Transform::from_translation(Vec3::Y * 100.).looking_at(Vec3::ZERO, Vec3::Y),
+        // at an angle so slopes are shaded
+        Transform::from_xyz(40., 100., 20.).looking_at(Vec3::ZERO, Vec3::Y),
     ));
     commands.spawn((Camera3d::default(), fly_cam::FlyCam));
     #[cfg(debug_assertions)]
@@ -161,15 +179,6 @@ fn spawn_camera(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
-fn spawn_map(mut commands: Commands, assets: Res<CellAssets>, asset_server: Res<AssetServer>) {
-    commands.spawn((
-        Transform::default(),
-        Visibility::default(),
-        Terrain::new(0),
-        Cell,
-    ));
-}
-
 #[derive(Component)]
 struct Root;
 
@@ -182,26 +191,31 @@ fn add_root(mut commands: Commands, scenes: Query<Entity, Added<SceneRoot>>) {
 fn ray_casting(
     mut commands: Commands,
     mut clicks: EventReader<Pointer<Click>>,
-    terrain: Query<(), With<Terrain>>,
+    chunks: Query<(), With<terrain::Chunk>>,
+    planes: Query<&terrain::Plane>,
     player: Query<Entity, With<Player>>,
 ) {
     for click in clicks.read() {
         if click.button != PointerButton::Primary {
             continue;
         }
-        if !terrain.contains(click.target) {
+        let plane = if let Ok(plane) = planes.get(click.target) {
+            plane.0
+        } else if chunks.contains(click.target) {
+            0
+        } else {
             continue;
-        }
+        };
         let cell = if let Some(pos) = click.hit.position {
-            let mut target = pos.round();
-            target.y = 0.;
-            target.as_ivec3()
+            let mut target = pos.round().as_ivec3();
+            target.y = plane;
+            target
         } else {
             error!("Click has no position data");
             continue;
         };
         let player = player.single();
-        commands.entity(player).insert(Target(cell));
+        commands.entity(player).insert(Target(Goal::Tile(cell)));
     }
 }
 
@@ -219,81 +233,114 @@ fn run_player_action(world: &mut World) {
     }
 }
 
+/// asks for a path whenever an entity gets a new [`Target`],
+/// targets that can't be walked to come back as a [`path_finding::PathError`]
 fn build_path(
-    mut commands: Commands,
-    cells: Query<&MoveCost, With<Cell>>,
-    mut path_finder: Query<(Entity, &mut Path, &Target, &NextCell, &PastCell)>,
-    map: Res<CellIdToEntity>,
+    path_finder: Query<(Entity, &Target, &NextCell, &PastCell), Changed<Target>>,
+    mut requests: EventWriter<path_finding::PathRequest>,
 ) {
-    for (entity, mut path, target, next, past) in &mut path_finder {
-        let Some(cell_e) = map.get_by_id(&target.0) else {
-            warn!("Cell ({}) not in map", target.0);
-            continue;
-        };
-        if let Ok(cost) = cells.get(cell_e) {
-            if cost.0.is_infinite() {
-                trace!("Clicked Impassable Cell");
-                continue;
-            }
-        } else {
-            warn!("Cell Has not MoveCost");
-            continue;
-        }
-
+    for (entity, target, next, past) in &path_finder {
         let start = if let Some(next) = next.0 {
             next
         } else {
             past.cell
         };
+        requests.send(path_finding::PathRequest {
+            entity,
+            start,
+            goal: target.0.clone(),
+        });
+    }
+}
 
-        let Some((new_path, check)) = path_finding::a_star_debug(start, target.0, &cells, &map)
-        else {
-            error!("path find failed");
-            commands.entity(entity).remove::<Target>();
+/// puts finished paths on the entities that asked for them,
+/// the [`Target`] stays until then so a new one can replace it
+fn apply_path(
+    mut commands: Commands,
+    mut results: EventReader<path_finding::PathResult>,
+    mut path_finder: Query<(&mut Path, &Target)>,
+    player: Query<(), With<Player>>,
+    mut messages: EventWriter<ui::GameMessage>,
+) {
+    for result in results.read() {
+        // a newer request is on its way
+        let Ok((mut path, target)) = path_finder.get_mut(result.entity) else {
             continue;
         };
+        if target.0 != result.goal {
+            continue;
+        }
+        // the only place the player is told, whatever they were walking to
+        let error = match &result.path {
+            Err(error) => Some(*error),
+            Ok(_) => result.partial,
+        };
+        if let Some(error) = error {
+            if player.contains(result.entity) {
+                messages.send(ui::GameMessage(error.feedback().into()));
+            }
+        }
+        let new_path = match &result.path {
+            Ok(new_path) => new_path,
+            Err(error) => {
+                debug!("no path for {}: {error}", result.entity);
+                commands
+                    .entity(result.entity)
+                    .remove::<(Target, path_finding::Waypoints)>();
+                continue;
+            }
+        };
 
         path.0.clear();
-        path.0.extend(new_path);
-        commands.entity(entity).remove::<Target>();
+        path.0.extend(new_path.iter().copied());
+        commands.entity(result.entity).remove::<Target>();
+        if let Some(waypoints) = &result.waypoints {
+            commands.entity(result.entity).insert(waypoints.clone());
+        } else {
+            commands
+                .entity(result.entity)
+                .remove::<path_finding::Waypoints>();
+        }
     }
 }
 
 #[derive(Component)]
 struct File(&'static str);
 
-fn spawn_character(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let char = [
-        "characters/character-female-a.glb#Scene0",
-        "characters/character-female-b.glb#Scene0",
-        "characters/character-female-c.glb#Scene0",
-        "characters/character-female-d.glb#Scene0",
-        "characters/character-female-e.glb#Scene0",
-        "characters/character-female-f.glb#Scene0",
-        "characters/character-male-a.glb#Scene0",
-        "characters/character-male-b.glb#Scene0",
-        "characters/character-male-c.glb#Scene0",
-        "characters/character-male-d.glb#Scene0",
-        "characters/character-male-e.glb#Scene0",
-        "characters/character-male-f.glb#Scene0",
-    ];
-    let mut rng = rand::thread_rng();
-
-    let main = *char.choose(&mut rng).expect(">= one str");
-    commands.spawn((
-        File(main),
-        SceneRoot(asset_server.load(main)),
-        Player,
+const CHARACTERS: [&str; 12] = [
+    "characters/character-female-a.glb#Scene0",
+    "characters/character-female-b.glb#Scene0",
+    "characters/character-female-c.glb#Scene0",
+    "characters/character-female-d.glb#Scene0",
+    "characters/character-female-e.glb#Scene0",
+    "characters/character-female-f.glb#Scene0",
+    "characters/character-male-a.glb#Scene0",
+    "characters/character-male-b.glb#Scene0",
+    "characters/character-male-c.glb#Scene0",
+    "characters/character-male-d.glb#Scene0",
+    "characters/character-male-e.glb#Scene0",
+    "characters/character-male-f.glb#Scene0",
+];
+
+fn character(file: &'static str, asset_server: &AssetServer) -> impl Bundle {
+    (
+        File(file),
+        SceneRoot(asset_server.load(file)),
         Animation::Idle,
         Path::default(),
-    ));
-    for _ in 0..10 {
-        let main = *char.choose(&mut rng).expect(">= one str");
+    )
+}
+
+fn spawn_character(mut commands: Commands, asset_server: Res<AssetServer>, seed: Res<WorldSeed>) {
+    let mut rng = seed.rng(Stream::Characters);
+
+    let main = *CHARACTERS.choose(&mut rng).expect(">= one str");
+    commands.spawn((character(main, &asset_server), Player));
+    for index in 0..10 {
+        let main = *CHARACTERS.choose(&mut rng).expect(">= one str");
         commands.spawn((
-            File(main),
-            Animation::Idle,
-            SceneRoot(asset_server.load(*char.choose(&mut rng).expect(">= one str"))),
-            Path::default(),
+            character(main, &asset_server),
+            EntityRng(seed.entity_rng(Stream::Wander, index)),
         ));
     }
 }
@@ -305,7 +352,7 @@ struct Player;
 struct NextCell(Option<IVec3>);
 
 #[derive(Component)]
-struct Target(IVec3);
+struct Target(Goal);
 
 #[derive(Component, Default)]
 struct PastCell {
@@ -317,21 +364,37 @@ struct PastCell {
 #[require(NextCell, PastCell)]
 struct Path(std::collections::VecDeque<IVec3>);
 
+/// where a character stands on a cell,
+/// ground cells come from the terrain so they work outside loaded chunks
+fn cell_position(
+    cell: IVec3,
+    terrain: &terrain::Terrain,
+    map: &CellIdToEntity,
+    cells: &Query<&GlobalTransform, With<Cell>>,
+) -> Option<Vec3> {
+    if cell.y == 0 {
+        return terrain.tile_position(cell);
+    }
+    let entity = map.get_by_id(&cell)?;
+    cells.get(entity).ok().map(|pos| pos.translation())
+}
+
 fn move_entity(
     time: Res<Time>,
-    mut entities: Query<
-        (
-            &mut Transform,
-            &mut NextCell,
-            &mut PastCell,
-            &mut Path,
-            &mut Animation,
-        ),
-        Without<Cell>,
-    >,
-    cells: Query<&Transform, With<Cell>>,
+    mut entities: Query<(
+        &mut Transform,
+        &mut NextCell,
+        &mut PastCell,
+        &mut Path,
+        &mut Animation,
+    )>,
+    terrain: Query<&terrain::Terrain>,
+    cells: Query<&GlobalTransform, With<Cell>>,
     map: Res<CellIdToEntity>,
 ) {
+    let Ok(terrain) = terrain.get_single() else {
+        return;
+    };
     for (mut pos, mut target, mut past, mut next, mut animation) in &mut entities {
         if target.0.is_none() && !next.0.is_empty() {
             target.0 = next.0.pop_front();
@@ -339,28 +402,20 @@ fn move_entity(
         let Some(target_cell) = target.0 else {
             continue;
         };
-        let Some(target_e) = map.get_by_id(&target_cell) else {
+        let Some(mut target_pos) = cell_position(target_cell, terrain, &map, &cells) else {
             warn!("Target ({}) not in map", target_cell);
             continue;
         };
-        let Ok(mut target_pos) = cells.get(target_e).cloned() else {
-            warn!("Target ({}) not is not an entity", target_cell);
-            continue;
-        };
         let current = pos.translation;
-        if current.distance_squared(target_pos.translation) < 0.001 {
+        if current.distance_squared(target_pos) < 0.001 {
             past.cell = target_cell;
             if let Some(next) = next.0.pop_front() {
                 past.start_time = time.elapsed_secs();
                 target.0 = Some(next);
-                let Some(target_e) = map.get_by_id(&next) else {
+                let Some(next_pos) = cell_position(next, terrain, &map, &cells) else {
                     warn!("Target ({}) not in map", next);
                     continue;
                 };
-                let Ok(next_pos) = cells.get(target_e).cloned() else {
-                    warn!("Target ({}) not is not an entity", next);
-                    continue;
-                };
                 target_pos = next_pos;
                 if *animation != Animation::Walk {
                     *animation = Animation::Walk;
@@ -374,39 +429,34 @@ fn move_entity(
             };
         }
 
-        let Some(past_e) = map.get_by_id(&past.cell) else {
+        let Some(past_pos) = cell_position(past.cell, terrain, &map, &cells) else {
             warn!("Target ({}) not in map", past.cell);
             continue;
         };
-        let Ok(past_pos) = cells.get(past_e) else {
-            warn!("Target ({}) not is not an entity", past.cell);
-            continue;
-        };
 
-        let target = past_pos.translation.lerp(
-            target_pos.translation,
+        let mut target = past_pos.lerp(
+            target_pos,
             ((time.elapsed_secs() - past.start_time) * 10.).clamp(0., 0.999),
         );
+        // follow the ground between tiles instead of cutting through it
+        if past.cell.y == 0 && target_cell.y == 0 {
+            target.y = terrain.hight_at(target.xz()).unwrap_or(target.y);
+        }
         pos.translation = target;
-        pos.look_at(target_pos.translation, Vec3::Y);
+        pos.look_at(target_pos, Vec3::Y);
         pos.rotate_local_y(f32::consts::PI);
     }
 }
 
 fn random_move(
     mut commands: Commands,
-    entities: Query<(&NextCell, &PastCell, Entity), Without<Player>>,
+    mut entities: Query<(&NextCell, &PastCell, &mut EntityRng, Entity), Without<Player>>,
 ) {
-    for (next, past, entity) in &entities {
+    for (next, past, mut rng, entity) in &mut entities {
         if next.0.is_none() {
-            commands.entity(entity).insert(Target(
-                past.cell
-                    + IVec3::new(
-                        rand::thread_rng().gen_range(-10..10),
-                        0,
-                        rand::thread_rng().gen_range(-10..10),
-                    ),
-            ));
+            commands.entity(entity).insert(Target(Goal::Tile(
+                past.cell + IVec3::new(rng.0.gen_range(-10..10), 0, rng.0.gen_range(-10..10)),
+            )));
         }
     }
 }
@@ -0,0 +1,568 @@
+use std::{
+    collections::{BinaryHeap, VecDeque},
+    sync::Arc,
+};
+
+use bevy::{
+    prelude::*,
+    utils::hashbrown::{HashMap, HashSet},
+};
+
+use crate::{
+    collision::{step_blocked, Layer, Side},
+    terrain::Transitions,
+    NextCell, PastCell, Path, Target,
+};
+
+use super::{a_star, a_star_within, Goal, NavGrid, NavSystems, Open, PathError};
+
+/// tiles along each side of a cluster
+const CLUSTER_SIZE: i32 = 16;
+/// paths further than this are found through the clusters
+const LONG_PATH: i32 = CLUSTER_SIZE * 2;
+/// clusters rebuilt each frame, a new map is built over a few frames
+const CLUSTERS_PER_FRAME: usize = 256;
+/// open stretches of a border longer than this get an entrance at each end instead of the middle
+const WIDE_ENTRANCE: usize = 6;
+/// the next waypoint is refined once fewer steps than this are left
+const REFINE_AHEAD: usize = 8;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<NavClusters>().add_systems(
+        Update,
+        (
+            update_clusters.in_set(NavSystems::Clusters),
+            refine_paths.in_set(NavSystems::Paths),
+        ),
+    );
+}
+
+/// The rest of a long path, entrances between clusters the character still has to get to
+/// and then the goal. Each one is turned into steps on the [`Path`] when the character gets close.
+#[derive(Component, Clone, Debug)]
+pub struct Waypoints {
+    pub tiles: VecDeque<IVec3>,
+    pub goal: Goal,
+}
+
+/// The map cut into clusters with paths between their entrances worked out ahead of time,
+/// so long paths only search the entrances and the tiles near each end.
+/// The maps are shared with the snapshots of searches still running
+#[derive(Resource, Default)]
+pub struct NavClusters {
+    /// pairs of tiles across the east and north border of a cluster and the cost of the step
+    borders: Arc<HashMap<(IVec3, Side), Vec<(IVec3, IVec3, f32)>>>,
+    /// the cost between every two entrances of a cluster that are connected inside it
+    inside: Arc<HashMap<IVec3, HashMap<IVec3, Vec<(IVec3, f32)>>>>,
+    /// steps out of a cluster, across borders and transitions
+    links: Arc<HashMap<IVec3, Vec<(IVec3, f32)>>>,
+    /// both ends of every transition that can be used
+    transition_ends: Arc<Vec<IVec3>>,
+    /// clusters that have to be rebuilt
+    dirty: HashSet<IVec3>,
+}
+
+/// the tiles of one cluster
+struct Bounds {
+    min: IVec2,
+    size: IVec2,
+    plane: i32,
+}
+
+impl Bounds {
+    /// clusters are keyed by their position in clusters with y being the plane
+    fn cluster(grid: &NavGrid, tile: IVec3) -> IVec3 {
+        let cluster = (tile.xz() - grid.min).div_euclid(IVec2::splat(CLUSTER_SIZE));
+        IVec3::new(cluster.x, tile.y, cluster.y)
+    }
+
+    fn new(grid: &NavGrid, cluster: IVec3) -> Bounds {
+        let min = grid.min + cluster.xz() * CLUSTER_SIZE;
+        Bounds {
+            min,
+            size: (grid.min + grid.size - min).min(IVec2::splat(CLUSTER_SIZE)),
+            plane: cluster.y,
+        }
+    }
+
+    fn contains(&self, tile: IVec3) -> bool {
+        let local = tile.xz() - self.min;
+        tile.y == self.plane && local.cmpge(IVec2::ZERO).all() && local.cmplt(self.size).all()
+    }
+
+    fn local(&self, tile: IVec3) -> usize {
+        let local = tile.xz() - self.min;
+        (local.y * self.size.x + local.x) as usize
+    }
+
+    /// the cost of a tile in costs from [`cluster_costs`], infinite outside the cluster
+    fn cost(&self, costs: &[f32], tile: IVec3) -> f32 {
+        if self.contains(tile) {
+            costs[self.local(tile)]
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+impl NavClusters {
+    /// a copy for searches running in the background, without the clusters waiting to be rebuilt
+    pub fn snapshot(&self) -> NavClusters {
+        NavClusters {
+            borders: self.borders.clone(),
+            inside: self.inside.clone(),
+            links: self.links.clone(),
+            transition_ends: self.transition_ends.clone(),
+            dirty: HashSet::new(),
+        }
+    }
+
+    fn all_clusters(grid: &NavGrid) -> impl Iterator<Item = IVec3> + '_ {
+        let count = (grid.size + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+        grid.planes.iter().flat_map(move |plane| {
+            (0..count.y).flat_map(move |z| (0..count.x).map(move |x| IVec3::new(x, *plane, z)))
+        })
+    }
+
+    /// finds the entrances across the east or north border of a cluster
+    fn scan_border(grid: &NavGrid, cluster: IVec3, side: Side) -> Vec<(IVec3, IVec3, f32)> {
+        let bounds = Bounds::new(grid, cluster);
+        let (start, along, length) = match side {
+            Side::East => (
+                IVec3::new(bounds.min.x + bounds.size.x - 1, cluster.y, bounds.min.y),
+                IVec3::Z,
+                bounds.size.y,
+            ),
+            _ => (
+                IVec3::new(bounds.min.x, cluster.y, bounds.min.y + bounds.size.y - 1),
+                IVec3::X,
+                bounds.size.x,
+            ),
+        };
+        let across = side.offset();
+        let open = |a: IVec3| {
+            let b = a + across;
+            grid.walkable(a)
+                && grid.walkable(b)
+                && !step_blocked(a, b, Layer::Movement, |tile| grid.edges(tile))
+        };
+        let mut entrances = Vec::new();
+        let mut entrance = |run: &[IVec3]| {
+            let tiles = if run.len() > WIDE_ENTRANCE {
+                vec![run[0], run[run.len() - 1]]
+            } else {
+                vec![run[run.len() / 2]]
+            };
+            for a in tiles {
+                let b = a + across;
+                let cost = (grid.cost(a).unwrap_or(f32::INFINITY)
+                    + grid.cost(b).unwrap_or(f32::INFINITY))
+                    / 2.;
+                entrances.push((a, b, cost));
+            }
+        };
+        let mut run = Vec::new();
+        for i in 0..length {
+            let a = start + along * i;
+            if open(a) {
+                run.push(a);
+            } else if !run.is_empty() {
+                entrance(&run);
+                run.clear();
+            }
+        }
+        if !run.is_empty() {
+            entrance(&run);
+        }
+        entrances
+    }
+
+    /// the entrances of a cluster, tiles inside it that lead to another cluster
+    fn entrances(&self, grid: &NavGrid, cluster: IVec3) -> Vec<IVec3> {
+        let mut entrances = Vec::new();
+        for (key, inside_is_a) in [
+            ((cluster, Side::East), true),
+            ((cluster, Side::North), true),
+            ((cluster - IVec3::X, Side::East), false),
+            ((cluster - IVec3::Z, Side::North), false),
+        ] {
+            for (a, b, _) in self.borders.get(&key).into_iter().flatten() {
+                entrances.push(if inside_is_a { *a } else { *b });
+            }
+        }
+        entrances.extend(
+            self.transition_ends
+                .iter()
+                .filter(|tile| Bounds::cluster(grid, **tile) == cluster),
+        );
+        entrances.sort_by_key(|tile| (tile.x, tile.z));
+        entrances.dedup();
+        entrances
+    }
+
+    /// rebuilds some dirty clusters, the rest wait for the next frame
+    fn rebuild(&mut self, grid: &NavGrid, transitions: &Transitions) {
+        let batch = self
+            .dirty
+            .iter()
+            .take(CLUSTERS_PER_FRAME)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut inside = HashSet::new();
+        let borders = Arc::make_mut(&mut self.borders);
+        for cluster in &batch {
+            self.dirty.remove(cluster);
+            for (key, side) in [
+                (*cluster, Side::East),
+                (*cluster, Side::North),
+                (*cluster - IVec3::X, Side::East),
+                (*cluster - IVec3::Z, Side::North),
+            ] {
+                if key.x < 0 || key.z < 0 {
+                    continue;
+                }
+                let entrances = NavClusters::scan_border(grid, key, side);
+                if entrances.is_empty() {
+                    borders.remove(&(key, side));
+                } else {
+                    borders.insert((key, side), entrances);
+                }
+            }
+            // the entrances of the clusters around it moved too
+            inside.insert(*cluster);
+            for side in Side::ALL {
+                inside.insert(*cluster + side.offset());
+            }
+        }
+
+        let mut links = HashMap::<IVec3, Vec<(IVec3, f32)>>::new();
+        let mut transition_ends = Vec::new();
+        for (a, b, cost) in borders.values().flatten() {
+            links.entry(*a).or_default().push((*b, *cost));
+            links.entry(*b).or_default().push((*a, *cost));
+        }
+        for transition in transitions.all() {
+            let (Some(a), Some(b)) = (grid.cost(transition.a), grid.cost(transition.b)) else {
+                continue;
+            };
+            if a.is_finite() && b.is_finite() {
+                let cost = (a + b) / (2. / transition.kind.cost());
+                links
+                    .entry(transition.a)
+                    .or_default()
+                    .push((transition.b, cost));
+                links
+                    .entry(transition.b)
+                    .or_default()
+                    .push((transition.a, cost));
+                transition_ends.extend([transition.a, transition.b]);
+            }
+        }
+        self.links = Arc::new(links);
+        self.transition_ends = Arc::new(transition_ends);
+
+        for cluster in inside {
+            let bounds = Bounds::new(grid, cluster);
+            if cluster.x < 0
+                || cluster.z < 0
+                || bounds.size.cmple(IVec2::ZERO).any()
+                || !grid.planes.contains(&cluster.y)
+            {
+                continue;
+            }
+            let entrances = self.entrances(grid, cluster);
+            let mut edges = HashMap::new();
+            for from in &entrances {
+                let costs = cluster_costs(grid, transitions, &[*from], &bounds);
+                let reachable = entrances
+                    .iter()
+                    .filter(|to| *to != from)
+                    .filter_map(|to| {
+                        let cost = bounds.cost(&costs, *to);
+                        cost.is_finite().then_some((*to, cost))
+                    })
+                    .collect::<Vec<_>>();
+                edges.insert(*from, reachable);
+            }
+            Arc::make_mut(&mut self.inside).insert(cluster, edges);
+        }
+    }
+
+    fn neighbours(&self, grid: &NavGrid, node: IVec3) -> impl Iterator<Item = &(IVec3, f32)> {
+        self.inside
+            .get(&Bounds::cluster(grid, node))
+            .and_then(|edges| edges.get(&node))
+            .into_iter()
+            .flatten()
+            .chain(self.links.get(&node).into_iter().flatten())
+    }
+}
+
+/// the cost from the closest of `from` to every tile of the cluster without leaving it
+fn cluster_costs(
+    grid: &NavGrid,
+    transitions: &Transitions,
+    from: &[IVec3],
+    bounds: &Bounds,
+) -> Vec<f32> {
+    let mut costs = vec![f32::INFINITY; (bounds.size.x * bounds.size.y) as usize];
+    let mut open = BinaryHeap::new();
+    for tile in from {
+        let Some(start) = grid.index(*tile).filter(|_| bounds.contains(*tile)) else {
+            continue;
+        };
+        costs[bounds.local(*tile)] = 0.;
+        open.push(Open {
+            f_score: 0.,
+            g_score: 0.,
+            index: start as u32,
+        });
+    }
+    let mut steps = Vec::new();
+    while let Some(Open { g_score, index, .. }) = open.pop() {
+        let current = grid.tile(index as usize);
+        if g_score > costs[bounds.local(current)] {
+            continue;
+        }
+        grid.steps(current, transitions, &mut steps);
+        for (n, cost) in steps.iter().copied() {
+            let tile = grid.tile(n);
+            if !bounds.contains(tile) {
+                continue;
+            }
+            let g = g_score + (grid.costs[index as usize] + grid.costs[n]) / cost;
+            if g < costs[bounds.local(tile)] {
+                costs[bounds.local(tile)] = g;
+                open.push(Open {
+                    f_score: g,
+                    g_score: g,
+                    index: n as u32,
+                });
+            }
+        }
+    }
+    costs
+}
+
+/// the entrances a long path goes through after `start`, ending with the tile of the goal
+fn hpa_star(
+    start: IVec3,
+    goal: &Goal,
+    grid: &NavGrid,
+    clusters: &NavClusters,
+    transitions: &Transitions,
+) -> Result<Vec<IVec3>, PathError> {
+    let (start_index, end_index) = grid.ends(start, goal)?;
+    let end = grid.tile(end_index);
+    let start_cluster = Bounds::cluster(grid, start);
+    let end_cluster = Bounds::cluster(grid, end);
+    let start_bounds = Bounds::new(grid, start_cluster);
+    let end_bounds = Bounds::new(grid, end_cluster);
+    // the tiles of the end cluster the path can stop on
+    let arrivals = goal
+        .arrivals()
+        .filter(|tile| end_bounds.contains(*tile) && grid.walkable(*tile))
+        .collect::<Vec<_>>();
+    if arrivals.is_empty() {
+        return Err(PathError::Unreachable(end));
+    }
+    // the ends join the graph through the entrances of their clusters
+    let from_start = cluster_costs(grid, transitions, &[start], &start_bounds);
+    let to_end = cluster_costs(grid, transitions, &arrivals, &end_bounds);
+    let exits = clusters
+        .entrances(grid, end_cluster)
+        .into_iter()
+        .filter_map(|tile| {
+            let cost = end_bounds.cost(&to_end, tile);
+            cost.is_finite().then_some((tile, cost))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let end_f32 = end.as_vec3();
+    let mut g_score = HashMap::new();
+    let mut from = HashMap::new();
+    let mut open = BinaryHeap::new();
+    // clusters from before the grid changed can lead off it
+    let push = |open: &mut BinaryHeap<Open>, tile: IVec3, g: f32| -> Result<(), PathError> {
+        let index = grid.index(tile).ok_or(PathError::Unreachable(end))?;
+        open.push(Open {
+            f_score: g + tile.as_vec3().distance(end_f32) * 3.,
+            g_score: g,
+            index: index as u32,
+        });
+        Ok(())
+    };
+    g_score.insert(start, 0.);
+    push(&mut open, start, 0.)?;
+    while let Some(Open {
+        g_score: g, index, ..
+    }) = open.pop()
+    {
+        let current = grid.tile(index as usize);
+        if index as usize == end_index {
+            let mut waypoints = vec![current];
+            let mut current = current;
+            while let Some(previous) = from.get(&current) {
+                if *previous == start {
+                    break;
+                }
+                waypoints.push(*previous);
+                current = *previous;
+            }
+            waypoints.reverse();
+            return Ok(waypoints);
+        }
+        if g > g_score.get(&current).copied().unwrap_or(f32::INFINITY) {
+            continue;
+        }
+        let mut visit = |to: IVec3, cost: f32| -> Result<(), PathError> {
+            let tentative_g = g + cost;
+            if tentative_g < g_score.get(&to).copied().unwrap_or(f32::INFINITY) {
+                g_score.insert(to, tentative_g);
+                from.insert(to, current);
+                push(&mut open, to, tentative_g)?;
+            }
+            Ok(())
+        };
+        if index as usize == start_index {
+            // the start can be an entrance itself
+            for (to, cost) in clusters.neighbours(grid, start) {
+                visit(*to, *cost)?;
+            }
+            for tile in clusters.entrances(grid, start_cluster) {
+                let cost = start_bounds.cost(&from_start, tile);
+                if cost.is_finite() {
+                    visit(tile, cost)?;
+                }
+            }
+            if start_cluster == end_cluster {
+                let direct = arrivals
+                    .iter()
+                    .map(|tile| start_bounds.cost(&from_start, *tile))
+                    .fold(f32::INFINITY, f32::min);
+                if direct.is_finite() {
+                    visit(end, direct)?;
+                }
+            }
+        } else {
+            for (to, cost) in clusters.neighbours(grid, current) {
+                visit(*to, *cost)?;
+            }
+            if let Some(cost) = exits.get(&current) {
+                visit(end, *cost)?;
+            }
+        }
+    }
+    Err(PathError::Unreachable(end))
+}
+
+/// A path to the goal, long paths only have the steps to the first waypoint with the rest left
+/// to be refined as the character walks. A path that stops short of the goal comes with why
+pub fn find_path(
+    start: IVec3,
+    goal: &Goal,
+    grid: &NavGrid,
+    clusters: &NavClusters,
+    transitions: &Transitions,
+) -> Result<(Vec<IVec3>, Option<Waypoints>, Option<PathError>), PathError> {
+    let direct =
+        || a_star(start, goal, grid, transitions).map(|(path, partial)| (path, None, partial));
+    let end = goal.tile().ok_or(PathError::NoGoal)?;
+    if start.xz().distance_squared(end.xz()) <= LONG_PATH * LONG_PATH {
+        return direct();
+    }
+    // goals the clusters don't lead to are walked towards as far as one search goes
+    let Ok(tiles) = hpa_star(start, goal, grid, clusters, transitions) else {
+        return direct();
+    };
+    let mut tiles = VecDeque::from(tiles);
+    // the goal is found by the last refine
+    tiles.pop_back();
+    let Some(first) = tiles.pop_front() else {
+        return direct();
+    };
+    let (path, partial) = a_star(start, &Goal::Tile(first), grid, transitions)?;
+    if partial.is_some() {
+        // the clusters are out of date
+        return direct();
+    }
+    let waypoints = Waypoints {
+        tiles,
+        goal: goal.clone(),
+    };
+    Ok((path, Some(waypoints), None))
+}
+
+fn update_clusters(
+    mut grid: ResMut<NavGrid>,
+    transitions: Res<Transitions>,
+    mut clusters: ResMut<NavClusters>,
+    mut old_transitions: Local<Vec<(IVec3, IVec3)>>,
+) {
+    // taking the changes doesn't change anything searches read, copies of the grid stay valid
+    let (reset, changed) = grid.bypass_change_detection().take_changes();
+    if reset {
+        *clusters = NavClusters::default();
+        let all = NavClusters::all_clusters(&grid).collect::<HashSet<_>>();
+        clusters.dirty = all;
+    }
+    for tile in changed {
+        let cluster = Bounds::cluster(&grid, tile);
+        clusters.dirty.insert(cluster);
+    }
+    if transitions.is_changed() {
+        let now = transitions
+            .all()
+            .map(|transition| (transition.a, transition.b))
+            .collect::<Vec<_>>();
+        for (a, b) in old_transitions.iter().chain(&now) {
+            let (a, b) = (Bounds::cluster(&grid, *a), Bounds::cluster(&grid, *b));
+            clusters.dirty.extend([a, b]);
+        }
+        *old_transitions = now;
+    }
+    if !clusters.dirty.is_empty() {
+        clusters.rebuild(&grid, &transitions);
+    }
+}
+
+/// turns the next waypoint into steps when a character is close to the end of its path,
+/// finds a new path if the way to it got blocked or the goal can't be reached from the last one
+fn refine_paths(
+    mut commands: Commands,
+    mut walkers: Query<(Entity, &mut Path, &mut Waypoints, &NextCell, &PastCell), Without<Target>>,
+    grid: Res<NavGrid>,
+    transitions: Res<Transitions>,
+) {
+    for (entity, mut path, mut waypoints, next, past) in &mut walkers {
+        if path.0.len() >= REFINE_AHEAD {
+            continue;
+        }
+        let (leg, last) = match waypoints.tiles.pop_front() {
+            Some(waypoint) => (Goal::Tile(waypoint), false),
+            None => (waypoints.goal.clone(), true),
+        };
+        let from = path.0.back().copied().or(next.0).unwrap_or(past.cell);
+        // a leg stays in the clusters it joins, so it never searches more than a few of them
+        let leg_clusters = leg
+            .arrivals()
+            .chain([from])
+            .map(|tile| Bounds::cluster(&grid, tile))
+            .collect::<HashSet<_>>();
+        let within = |tile| leg_clusters.contains(&Bounds::cluster(&grid, tile));
+        match a_star_within(from, &leg, &grid, &transitions, within) {
+            Ok((steps, None)) => {
+                path.0.extend(steps.into_iter().skip(1));
+                if last {
+                    commands.entity(entity).remove::<Waypoints>();
+                }
+            }
+            _ => {
+                commands
+                    .entity(entity)
+                    .insert(Target(waypoints.goal.clone()))
+                    .remove::<Waypoints>();
+            }
+        }
+    }
+}
@@ -0,0 +1,844 @@
+use std::{
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    utils::hashbrown::{HashMap, HashSet},
+};
+use thiserror::Error;
+
+use crate::{
+    collision::{step_blocked, CollisionMap, Edges, EdgesChanged, Layer},
+    terrain::{Plane, Transitions},
+    Cell, CellIdToEntity, NextCell, PastCell, Path,
+};
+
+/// tiles a search can look at before it gives up
+const MAX_STEPS: usize = 100_000;
+/// tiles searched without getting any closer before a goal nothing can stand on
+/// settles for the closest tile found
+const SETTLE_STEPS: usize = 2_000;
+
+const NEIGHBORS: [(IVec3, f32); 8] = [
+    (IVec3::new(0, 0, 1), 2.),     // up
+    (IVec3::new(-1, 0, 0), 2.),    // left
+    (IVec3::new(0, 0, -1), 2.),    // down
+    (IVec3::new(1, 0, 0), 2.),     // right
+    (IVec3::new(-1, 0, 1), 1.41),  // up left
+    (IVec3::new(-1, 0, -1), 1.41), // down left
+    (IVec3::new(1, 0, -1), 1.41),  // right down
+    (IVec3::new(1, 0, 1), 1.41),   // up right
+];
+
+mod hpa;
+mod requests;
+
+pub use hpa::{find_path, NavClusters, Waypoints};
+pub use requests::{PathRequest, PathResult};
+
+pub fn plugin(app: &mut App) {
+    app.add_plugins((hpa::plugin, requests::plugin))
+        .init_resource::<Occupancy>()
+        .init_resource::<NavGrid>()
+        .configure_sets(
+            Update,
+            (NavSystems::Grid, NavSystems::Clusters, NavSystems::Paths).chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                render_path,
+                (update_occupancy, update_nav_grid).in_set(NavSystems::Grid),
+            ),
+        );
+}
+
+/// The order path finding runs in each frame, so searches always see
+/// clusters built from the grid as it is that frame
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum NavSystems {
+    /// the [`NavGrid`] catches up with cells and collision
+    Grid,
+    /// the [`NavClusters`] catch up with the grid
+    Clusters,
+    /// path requests are searched
+    Paths,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct MoveCost(pub f32);
+
+impl Default for MoveCost {
+    fn default() -> Self {
+        MoveCost(10.)
+    }
+}
+
+/// How an object changes the cost of walking over the tiles it stands on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Passability {
+    /// decoration, doesn't change anything
+    #[default]
+    Walkable,
+    /// costs this many times more to cross, like bushes
+    Slow(f32),
+    /// can't be walked onto, like trees and rocks
+    Blocked,
+}
+
+impl Passability {
+    fn factor(self) -> f32 {
+        match self {
+            Passability::Walkable => 1.,
+            Passability::Slow(factor) => factor,
+            Passability::Blocked => f32::INFINITY,
+        }
+    }
+}
+
+/// The tiles an object stands on, their [`MoveCost`] follows its passability while the entity exists
+#[derive(Component, Clone, Debug)]
+pub struct Footprint {
+    pub tiles: Vec<IVec3>,
+    pub passability: Passability,
+}
+
+/// How much objects change the cost of each tile, where objects overlap their changes stack
+#[derive(Default)]
+pub struct TileFactors(HashMap<IVec3, f32>);
+
+impl TileFactors {
+    /// stacks the passability of an object on the tiles it stands on
+    pub fn add(&mut self, tiles: impl IntoIterator<Item = IVec3>, passability: Passability) {
+        for tile in tiles {
+            *self.0.entry(tile).or_insert(1.) *= passability.factor();
+        }
+    }
+
+    /// the cost of a tile with the objects on it, `cost` is the cost without them
+    pub fn cost(&self, tile: IVec3, cost: f32) -> f32 {
+        match self.0.get(&tile) {
+            Some(factor) if factor.is_infinite() => f32::INFINITY,
+            Some(factor) => cost * factor,
+            None => cost,
+        }
+    }
+}
+
+/// The [`TileFactors`] of every object that is spawned
+#[derive(Resource, Default)]
+pub struct Occupancy {
+    factors: TileFactors,
+    by_entity: HashMap<Entity, Footprint>,
+    /// tiles whose cells need their cost updated
+    changed: HashSet<IVec3>,
+}
+
+impl Occupancy {
+    /// the cost of a tile with the objects on it, `cost` is the cost without them
+    pub fn cost(&self, tile: IVec3, cost: f32) -> f32 {
+        self.factors.cost(tile, cost)
+    }
+
+    /// the tiles that changed since the last call
+    pub fn take_changed(&mut self) -> HashSet<IVec3> {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn rebuild(&mut self) {
+        self.factors = TileFactors::default();
+        for footprint in self.by_entity.values() {
+            self.factors
+                .add(footprint.tiles.iter().copied(), footprint.passability);
+        }
+    }
+}
+
+fn update_occupancy(
+    footprints: Query<(Entity, &Footprint), Changed<Footprint>>,
+    mut removed: RemovedComponents<Footprint>,
+    mut occupancy: ResMut<Occupancy>,
+) {
+    let mut changed = Vec::new();
+    for entity in removed.read() {
+        if let Some(old) = occupancy.by_entity.remove(&entity) {
+            changed.extend(old.tiles);
+        }
+    }
+    for (entity, footprint) in &footprints {
+        changed.extend(footprint.tiles.iter().copied());
+        if let Some(old) = occupancy.by_entity.insert(entity, footprint.clone()) {
+            changed.extend(old.tiles);
+        }
+    }
+    if !changed.is_empty() {
+        occupancy.rebuild();
+        occupancy.changed.extend(changed);
+    }
+}
+
+/// The cost and blocked edges of every tile in flat arrays indexed by tile,
+/// so finding a path doesn't go through the ECS. Kept in sync with the [`MoveCost`] of cells
+/// and the [`CollisionMap`]. Ground tiles are filled in from the terrain so they can be
+/// walked on outside loaded chunks, other planes only where they have a cell.
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    /// the first tile of every layer
+    min: IVec2,
+    /// tiles along x and z
+    size: IVec2,
+    /// the plane of each layer, the ground is layer 0
+    planes: Vec<i32>,
+    /// shared with the snapshots of searches still running, copied when it changes under them
+    costs: Arc<Vec<f32>>,
+    edges: Arc<Vec<Edges>>,
+    /// the tile of every cell, so it can be cleared when the cell is despawned
+    cells: HashMap<Entity, IVec3>,
+    /// tiles whose cost or edges changed, for the clusters to catch up with
+    changed: Vec<IVec3>,
+    /// the layout changed, everything built on the grid has to start over
+    reset: bool,
+    edges_stale: bool,
+    /// scratch of searches that finished, for the next ones to reuse, shared with copies of the grid
+    scratch: Arc<Mutex<Vec<Scratch>>>,
+}
+
+impl NavGrid {
+    /// clears the grid to cover `size` tiles from `min` on every plane
+    pub fn resize(&mut self, min: IVec2, size: IVec2) {
+        let layer = (size.x * size.y) as usize;
+        *self = NavGrid {
+            min,
+            size,
+            planes: vec![0],
+            costs: Arc::new(vec![f32::INFINITY; layer]),
+            edges: Arc::new(vec![Edges::NONE; layer]),
+            reset: true,
+            edges_stale: true,
+            ..Default::default()
+        };
+    }
+
+    /// a copy for searches running in the background, only the layout and the tiles
+    /// without the bookkeeping of cells and changes. The tiles are shared until the grid changes
+    pub fn snapshot(&self) -> NavGrid {
+        NavGrid {
+            min: self.min,
+            size: self.size,
+            planes: self.planes.clone(),
+            costs: self.costs.clone(),
+            edges: self.edges.clone(),
+            scratch: self.scratch.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn index(&self, tile: IVec3) -> Option<usize> {
+        let local = tile.xz() - self.min;
+        if local.cmplt(IVec2::ZERO).any() || local.cmpge(self.size).any() {
+            return None;
+        }
+        let layer = self.planes.iter().position(|plane| *plane == tile.y)?;
+        Some(((layer as i32 * self.size.y + local.y) * self.size.x + local.x) as usize)
+    }
+
+    fn tile(&self, index: usize) -> IVec3 {
+        let index = index as i32;
+        let layer = index / (self.size.x * self.size.y);
+        let local = index % (self.size.x * self.size.y);
+        IVec3::new(
+            self.min.x + local % self.size.x,
+            self.planes[layer as usize],
+            self.min.y + local / self.size.x,
+        )
+    }
+
+    /// the cost of a tile, infinite where it can't be walked on, None outside the grid
+    pub fn cost(&self, tile: IVec3) -> Option<f32> {
+        self.index(tile).map(|index| self.costs[index])
+    }
+
+    pub fn set_cost(&mut self, tile: IVec3, cost: f32) {
+        if !self.planes.contains(&tile.y) {
+            self.planes.push(tile.y);
+            let layer = (self.size.x * self.size.y) as usize;
+            let (costs, edges) = (self.costs.len(), self.edges.len());
+            Arc::make_mut(&mut self.costs).resize(costs + layer, f32::INFINITY);
+            Arc::make_mut(&mut self.edges).resize(edges + layer, Edges::NONE);
+            self.reset = true;
+            self.edges_stale = true;
+        }
+        let Some(index) = self.index(tile) else {
+            warn!("Cell({}) is outside the nav grid", tile);
+            return;
+        };
+        if self.costs[index] != cost {
+            Arc::make_mut(&mut self.costs)[index] = cost;
+            // after a reset everything is rebuilt anyway
+            if !self.reset {
+                self.changed.push(tile);
+            }
+        }
+    }
+
+    /// whether the layout was reset and the tiles that changed since the last call
+    fn take_changes(&mut self) -> (bool, Vec<IVec3>) {
+        (
+            std::mem::take(&mut self.reset),
+            std::mem::take(&mut self.changed),
+        )
+    }
+
+    /// the indices of the start and the tile of the goal
+    fn ends(&self, start: IVec3, goal: &Goal) -> Result<(usize, usize), PathError> {
+        let end = goal.tile().ok_or(PathError::NoGoal)?;
+        let start_index = self.index(start).ok_or(PathError::StartOffMap(start))?;
+        let end_index = self.index(end).ok_or(PathError::TargetOffMap(end))?;
+        Ok((start_index, end_index))
+    }
+
+    fn walkable(&self, tile: IVec3) -> bool {
+        self.cost(tile).is_some_and(f32::is_finite)
+    }
+
+    fn edges(&self, tile: IVec3) -> Edges {
+        self.index(tile)
+            .map_or(Edges::NONE, |index| self.edges[index])
+    }
+
+    /// the tiles a path can step to from `current` and what the step cost is divided by.
+    /// Steps can't cross blocked edges and diagonals can't cut the corner of an impassable tile,
+    /// the only way between planes is through a transition
+    fn steps(&self, current: IVec3, transitions: &Transitions, out: &mut Vec<(usize, f32)>) {
+        out.clear();
+        for (offset, cost) in NEIGHBORS {
+            let Some(index) = self.index(current + offset) else {
+                continue;
+            };
+            if step_blocked(current, current + offset, Layer::Movement, |tile| {
+                self.edges(tile)
+            }) {
+                continue;
+            }
+            if offset.x == 0
+                || offset.z == 0
+                || (self.walkable(current + offset.with_z(0))
+                    && self.walkable(current + offset.with_x(0)))
+            {
+                out.push((index, cost));
+            }
+        }
+        for (to, kind) in transitions.from(current) {
+            if let Some(index) = self.index(*to) {
+                out.push((index, 2. / kind.cost()));
+            }
+        }
+    }
+}
+
+fn update_nav_grid(
+    cells: Query<(Entity, &Transform, Option<&Plane>, &MoveCost), (With<Cell>, Changed<MoveCost>)>,
+    mut removed: RemovedComponents<Cell>,
+    collision: Res<CollisionMap>,
+    mut edge_events: EventReader<EdgesChanged>,
+    mut grid: ResMut<NavGrid>,
+) {
+    for entity in removed.read() {
+        // ground tiles keep the cost they had, unloading a chunk doesn't change them
+        if let Some(tile) = grid.cells.remove(&entity) {
+            if tile.y != 0 {
+                grid.set_cost(tile, f32::INFINITY);
+            }
+        }
+    }
+    for (entity, cell, plane, cost) in &cells {
+        let mut tile = cell.translation.round().as_ivec3();
+        tile.y = plane.map_or(0, |plane| plane.0);
+        grid.cells.insert(entity, tile);
+        grid.set_cost(tile, cost.0);
+    }
+    if grid.edges_stale {
+        grid.edges_stale = false;
+        edge_events.clear();
+        let mut all = vec![Edges::NONE; grid.edges.len()];
+        for (tile, edges) in collision.iter() {
+            if let Some(index) = grid.index(tile) {
+                all[index] = edges;
+            }
+        }
+        grid.edges = Arc::new(all);
+        // after a reset everything is rebuilt anyway
+        return;
+    }
+    for tile in edge_events.read().flat_map(|changed| &changed.0) {
+        let Some(index) = grid.index(*tile) else {
+            continue;
+        };
+        let edges = collision.edges(*tile);
+        if grid.edges[index] != edges {
+            Arc::make_mut(&mut grid.edges)[index] = edges;
+            grid.changed.push(*tile);
+        }
+    }
+}
+
+/// g scores and where each tile was reached from, kept between searches so they
+/// don't allocate. Entries from older searches are told apart by `search`.
+#[derive(Default)]
+struct Scratch {
+    g_score: Vec<f32>,
+    from: Vec<u32>,
+    searched: Vec<u32>,
+    search: u32,
+}
+
+impl Scratch {
+    const START: u32 = u32::MAX;
+
+    fn start(&mut self, len: usize) {
+        if self.g_score.len() != len {
+            *self = Scratch {
+                g_score: vec![f32::INFINITY; len],
+                from: vec![Scratch::START; len],
+                searched: vec![0; len],
+                search: 0,
+            };
+        }
+        self.search = self.search.wrapping_add(1);
+        if self.search == 0 {
+            self.searched.fill(0);
+            self.search = 1;
+        }
+    }
+
+    fn g_score(&self, index: usize) -> f32 {
+        if self.searched[index] == self.search {
+            self.g_score[index]
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    fn set(&mut self, index: usize, g_score: f32, from: u32) {
+        self.searched[index] = self.search;
+        self.g_score[index] = g_score;
+        self.from[index] = from;
+    }
+
+    fn path(&self, mut index: usize, grid: &NavGrid) -> Vec<IVec3> {
+        let mut out = vec![grid.tile(index)];
+        while self.from[index] != Scratch::START {
+            index = self.from[index] as usize;
+            out.push(grid.tile(index));
+        }
+        out.reverse();
+        out
+    }
+}
+
+/// a tile in the open list, the heap pops the lowest f score first
+struct Open {
+    f_score: f32,
+    g_score: f32,
+    index: u32,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.f_score.total_cmp(&self.f_score)
+    }
+}
+
+/// Where a path should end. If no path gets there it ends on the reachable tile
+/// closest to it, of tiles as close the one with the shorter path
+#[derive(Clone, Debug, PartialEq)]
+pub enum Goal {
+    Tile(IVec3),
+    /// any tile on or next to one of these, like the tiles of an object to use
+    Adjacent(Vec<IVec3>),
+}
+
+impl Goal {
+    /// the tile the path heads for, None when there are no tiles to go to
+    pub fn tile(&self) -> Option<IVec3> {
+        match self {
+            Goal::Tile(tile) => Some(*tile),
+            Goal::Adjacent(tiles) => tiles.first().copied(),
+        }
+    }
+
+    pub fn reached(&self, tile: IVec3) -> bool {
+        match self {
+            Goal::Tile(goal) => *goal == tile,
+            Goal::Adjacent(tiles) => tiles
+                .iter()
+                .any(|goal| goal.y == tile.y && (*goal - tile).abs().max_element() <= 1),
+        }
+    }
+
+    /// every tile that reaches the goal
+    fn arrivals(&self) -> impl Iterator<Item = IVec3> + '_ {
+        let (tiles, reach) = match self {
+            Goal::Tile(tile) => (std::slice::from_ref(tile), 0),
+            Goal::Adjacent(tiles) => (&tiles[..], 1),
+        };
+        tiles.iter().flat_map(move |tile| {
+            (-reach..=reach)
+                .flat_map(move |z| (-reach..=reach).map(move |x| *tile + IVec3::new(x, 0, z)))
+        })
+    }
+
+    /// straight line distance to the goal, 0 once it is reached
+    fn distance(&self, tile: IVec3) -> f32 {
+        match self {
+            Goal::Tile(goal) => goal.as_vec3().distance(tile.as_vec3()),
+            Goal::Adjacent(tiles) => tiles
+                .iter()
+                .map(|goal| (goal.as_vec3().distance(tile.as_vec3()) - 1.).max(0.))
+                .fold(f32::INFINITY, f32::min),
+        }
+    }
+}
+
+/// Why there is no path, the player is told with [`PathError::feedback`]
+#[derive(Debug, Error, Clone, Copy, PartialEq)]
+pub enum PathError {
+    #[error("the goal has no tiles")]
+    NoGoal,
+    #[error("start {0} is not on the map")]
+    StartOffMap(IVec3),
+    #[error("target {0} is not on the map")]
+    TargetOffMap(IVec3),
+    #[error("target {0} can't be walked on")]
+    TargetImpassable(IVec3),
+    #[error("no way to {0}")]
+    Unreachable(IVec3),
+    #[error("gave up after {MAX_STEPS} steps")]
+    TooManySteps,
+}
+
+impl PathError {
+    /// what the player says when their path fails
+    pub fn feedback(self) -> &'static str {
+        match self {
+            PathError::NoGoal | PathError::StartOffMap(_) | PathError::TargetOffMap(_) => {
+                "I can't go there."
+            }
+            PathError::TargetImpassable(_)
+            | PathError::Unreachable(_)
+            | PathError::TooManySteps => "I can't reach that!",
+        }
+    }
+}
+
+/// the tiles from `start` to the goal including both, or to the closest tile to it
+/// if it can't be reached together with why it wasn't
+pub fn a_star(
+    start: IVec3,
+    goal: &Goal,
+    grid: &NavGrid,
+    transitions: &Transitions,
+) -> Result<(Vec<IVec3>, Option<PathError>), PathError> {
+    a_star_within(start, goal, grid, transitions, |_| true)
+}
+
+/// [`a_star`] that only steps onto tiles `within` lets it
+fn a_star_within(
+    start: IVec3,
+    goal: &Goal,
+    grid: &NavGrid,
+    transitions: &Transitions,
+    within: impl Fn(IVec3) -> bool,
+) -> Result<(Vec<IVec3>, Option<PathError>), PathError> {
+    // searches running at the same time each take their own scratch
+    let mut scratch = grid
+        .scratch
+        .lock()
+        .ok()
+        .and_then(|mut pool| pool.pop())
+        .unwrap_or_default();
+    let path = search(start, goal, grid, transitions, within, &mut scratch);
+    if let Ok(mut pool) = grid.scratch.lock() {
+        pool.push(scratch);
+    }
+    path
+}
+
+fn search(
+    start: IVec3,
+    goal: &Goal,
+    grid: &NavGrid,
+    transitions: &Transitions,
+    within: impl Fn(IVec3) -> bool,
+    scratch: &mut Scratch,
+) -> Result<(Vec<IVec3>, Option<PathError>), PathError> {
+    let (start_index, end_index) = grid.ends(start, goal)?;
+    // nothing can stand on the goal, it is only walked towards
+    let possible = goal.arrivals().any(|tile| grid.walkable(tile));
+    scratch.start(grid.costs.len());
+    scratch.set(start_index, 0., Scratch::START);
+
+    let mut open = BinaryHeap::new();
+    open.push(Open {
+        f_score: 0.,
+        g_score: 0.,
+        index: start_index as u32,
+    });
+    let mut steps = Vec::with_capacity(NEIGHBORS.len());
+    let mut step = 0;
+    // distance to the goal, g score and index of the closest tile so far and when it was found
+    let mut closest = (goal.distance(start), 0., start_index);
+    let mut closest_step = 0;
+    while let Some(Open { g_score, index, .. }) = open.pop() {
+        let current_index = index as usize;
+        // a better way here was found after this was pushed
+        if g_score > scratch.g_score(current_index) {
+            continue;
+        }
+        let current = grid.tile(current_index);
+        if goal.reached(current) {
+            return Ok((scratch.path(current_index, grid), None));
+        }
+        step += 1;
+        let distance = goal.distance(current);
+        if (distance, g_score) < (closest.0, closest.1) {
+            closest = (distance, g_score, current_index);
+            closest_step = step;
+        }
+        if step > MAX_STEPS || (!possible && step - closest_step > SETTLE_STEPS) {
+            break;
+        }
+        let c_cost = grid.costs[current_index];
+        grid.steps(current, transitions, &mut steps);
+        for (n, cost) in steps.iter().copied() {
+            let tile = grid.tile(n);
+            if !within(tile) {
+                continue;
+            }
+            let tentative_g = g_score + (c_cost + grid.costs[n]) / cost;
+            if tentative_g < scratch.g_score(n) {
+                scratch.set(n, tentative_g, index);
+                open.push(Open {
+                    f_score: tentative_g + goal.distance(tile) * 3.,
+                    g_score: tentative_g,
+                    index: n as u32,
+                });
+            }
+        }
+    }
+    let end = grid.tile(end_index);
+    let error = if step > MAX_STEPS {
+        PathError::TooManySteps
+    } else if possible {
+        PathError::Unreachable(end)
+    } else {
+        PathError::TargetImpassable(end)
+    };
+    if closest.2 != start_index {
+        return Ok((scratch.path(closest.2, grid), Some(error)));
+    }
+    Err(error)
+}
+
+fn render_path(
+    paths: Query<(&GlobalTransform, &Path, &NextCell, &PastCell)>,
+    cells: Query<&Transform>,
+    mut gizmo: Gizmos,
+    map: Res<CellIdToEntity>,
+) {
+    for (pos, path, next, last) in &paths {
+        let Some(last) = map.get_by_id(&last.cell) else {
+            continue;
+        };
+        let Ok(last) = cells.get(last) else {
+            continue;
+        };
+        gizmo.sphere(
+            Isometry3d::from_translation(last.translation + Vec3::Y),
+            0.2,
+            Color::linear_rgb(0., 1., 0.),
+        );
+        if let Some(target) = next.0 {
+            let Some(next) = map.get_by_id(&target) else {
+                continue;
+            };
+            let Ok(next) = cells.get(next) else {
+                continue;
+            };
+            gizmo.sphere(
+                Isometry3d::from_translation(next.translation + Vec3::Y),
+                0.2,
+                Color::linear_rgb(1., 1., 0.),
+            );
+            let mut line = vec![pos.translation() + Vec3::Y, next.translation + Vec3::Y];
+            for segment in path.0.iter() {
+                let Some(next) = map.get_by_id(segment) else {
+                    continue;
+                };
+                let Ok(next) = cells.get(next) else {
+                    continue;
+                };
+                line.push(next.translation + Vec3::Y);
+            }
+            gizmo.linestrip(line, bevy::color::palettes::css::RED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        collision::Side,
+        terrain::{Transition, TransitionKind},
+    };
+
+    /// a square of ground tiles from the origin that all cost the same
+    fn open_grid(size: i32) -> NavGrid {
+        let mut grid = NavGrid::default();
+        grid.resize(IVec2::ZERO, IVec2::splat(size));
+        for z in 0..size {
+            for x in 0..size {
+                grid.set_cost(IVec3::new(x, 0, z), 1.);
+            }
+        }
+        grid
+    }
+
+    fn path(start: IVec3, goal: IVec3, grid: &NavGrid) -> (Vec<IVec3>, Option<PathError>) {
+        a_star(start, &Goal::Tile(goal), grid, &Transitions::default()).unwrap()
+    }
+
+    #[test]
+    fn index_and_tile_match() {
+        let mut grid = open_grid(4);
+        grid.set_cost(IVec3::new(1, 2, 3), 1.);
+        for tile in [IVec3::ZERO, IVec3::new(3, 0, 1), IVec3::new(1, 2, 3)] {
+            let index = grid.index(tile).unwrap();
+            assert_eq!(grid.tile(index), tile);
+        }
+        assert_eq!(grid.index(IVec3::new(4, 0, 0)), None);
+        assert_eq!(grid.index(IVec3::new(0, 0, -1)), None);
+        assert_eq!(grid.index(IVec3::new(0, 1, 0)), None);
+    }
+
+    #[test]
+    fn straight_path() {
+        let grid = open_grid(5);
+        let (steps, partial) = path(IVec3::ZERO, IVec3::new(4, 0, 0), &grid);
+        assert_eq!(
+            steps,
+            (0..5).map(|x| IVec3::new(x, 0, 0)).collect::<Vec<_>>()
+        );
+        assert_eq!(partial, None);
+    }
+
+    #[test]
+    fn open_list_pops_the_lowest_f_score() {
+        let mut open = BinaryHeap::new();
+        for (index, f_score) in [3., 1., 2.].into_iter().enumerate() {
+            open.push(Open {
+                f_score,
+                g_score: 0.,
+                index: index as u32,
+            });
+        }
+        let order: Vec<_> = std::iter::from_fn(|| open.pop().map(|open| open.index)).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn goes_around_a_blocked_edge() {
+        let mut grid = open_grid(3);
+        let (from, to) = (
+            grid.index(IVec3::ZERO).unwrap(),
+            grid.index(IVec3::X).unwrap(),
+        );
+        let edges = Arc::make_mut(&mut grid.edges);
+        edges[from] = Edges::fence(Side::East);
+        edges[to] = Edges::fence(Side::West);
+        let (steps, _) = path(IVec3::ZERO, IVec3::X, &grid);
+        // the diagonals into and out of the corner of the edge are blocked too
+        assert_eq!(
+            steps,
+            vec![IVec3::ZERO, IVec3::Z, IVec3::new(1, 0, 1), IVec3::X]
+        );
+    }
+
+    #[test]
+    fn diagonals_cant_cut_impassable_corners() {
+        let mut grid = open_grid(3);
+        let goal = IVec3::new(1, 0, 1);
+        assert_eq!(path(IVec3::ZERO, goal, &grid).0, vec![IVec3::ZERO, goal]);
+        grid.set_cost(IVec3::X, f32::INFINITY);
+        assert_eq!(
+            path(IVec3::ZERO, goal, &grid).0,
+            vec![IVec3::ZERO, IVec3::Z, goal]
+        );
+    }
+
+    #[test]
+    fn ends_on_the_closest_tile() {
+        let mut grid = open_grid(5);
+        let goal = IVec3::new(4, 0, 0);
+        // nothing can stand on the goal
+        grid.set_cost(goal, f32::INFINITY);
+        let (steps, partial) = path(IVec3::ZERO, goal, &grid);
+        assert_eq!(steps.last(), Some(&IVec3::new(3, 0, 0)));
+        assert_eq!(partial, Some(PathError::TargetImpassable(goal)));
+        // the goal can be stood on but a wall is in the way
+        grid.set_cost(goal, 1.);
+        for z in 0..5 {
+            grid.set_cost(IVec3::new(2, 0, z), f32::INFINITY);
+        }
+        let (steps, partial) = path(IVec3::ZERO, goal, &grid);
+        assert_eq!(steps.last(), Some(&IVec3::X));
+        assert_eq!(partial, Some(PathError::Unreachable(goal)));
+        // already as close as it gets
+        assert_eq!(
+            a_star(IVec3::X, &Goal::Tile(goal), &grid, &Transitions::default()),
+            Err(PathError::Unreachable(goal))
+        );
+    }
+
+    #[test]
+    fn changes_plane_through_a_transition() {
+        let mut grid = open_grid(3);
+        for x in 0..3 {
+            grid.set_cost(IVec3::new(x, 1, 0), 1.);
+        }
+        let (bottom, top) = (IVec3::ZERO, IVec3::Y);
+        let mut transitions = Transitions::default();
+        transitions.link(Transition {
+            a: bottom,
+            b: top,
+            kind: TransitionKind::Ladder,
+        });
+        let goal = IVec3::new(2, 1, 0);
+        let (steps, partial) =
+            a_star(IVec3::new(2, 0, 2), &Goal::Tile(goal), &grid, &transitions).unwrap();
+        assert_eq!(partial, None);
+        assert_eq!(steps.last(), Some(&goal));
+        let climb = steps.iter().position(|tile| *tile == bottom).unwrap();
+        assert_eq!(steps[climb + 1], top);
+        // nothing else leaves the ground
+        assert!(steps[..=climb].iter().all(|tile| tile.y == 0));
+        assert!(steps[climb + 1..].iter().all(|tile| tile.y == 1));
+    }
+}
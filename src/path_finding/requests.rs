@@ -0,0 +1,133 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+
+use crate::terrain::Transitions;
+
+use super::{find_path, Goal, NavClusters, NavGrid, NavSystems, PathError, Waypoints};
+
+/// searches started each frame, the rest wait in the queue
+const PATHS_PER_FRAME: usize = 4;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<PathQueue>()
+        .add_event::<PathRequest>()
+        .add_event::<PathResult>()
+        .add_systems(
+            Update,
+            (queue_paths, solve_paths).chain().in_set(NavSystems::Paths),
+        );
+}
+
+/// Asks for a path for an entity, a newer request for the same entity cancels this one
+#[derive(Event, Clone, Debug)]
+pub struct PathRequest {
+    pub entity: Entity,
+    pub start: IVec3,
+    pub goal: Goal,
+}
+
+/// A finished search
+#[derive(Event, Clone, Debug)]
+pub struct PathResult {
+    pub entity: Entity,
+    pub goal: Goal,
+    pub path: Result<Vec<IVec3>, PathError>,
+    /// the rest of a long path
+    pub waypoints: Option<Waypoints>,
+    /// why the path stops short of the goal, when it only gets as close as it can
+    pub partial: Option<PathError>,
+}
+
+/// Requests waiting for a search, oldest first, and the searches running in the background
+#[derive(Resource, Default)]
+pub struct PathQueue {
+    waiting: VecDeque<PathRequest>,
+    running: HashMap<Entity, Task<PathResult>>,
+}
+
+impl PathQueue {
+    /// drops the request of an entity. A search that already started runs to the end
+    /// in the background but its result is thrown away
+    pub fn cancel(&mut self, entity: Entity) {
+        self.waiting.retain(|request| request.entity != entity);
+        self.running.remove(&entity);
+    }
+}
+
+/// What searches in the background read, a new snapshot is taken when any of it changes
+/// so the resources can change while they run
+struct NavData {
+    grid: NavGrid,
+    clusters: NavClusters,
+    transitions: Transitions,
+}
+
+fn queue_paths(mut requests: EventReader<PathRequest>, mut queue: ResMut<PathQueue>) {
+    for request in requests.read() {
+        queue.cancel(request.entity);
+        queue.waiting.push_back(request.clone());
+    }
+}
+
+/// starts searches for the oldest requests on the async compute pool
+/// and sends the results of the ones that finished
+fn solve_paths(
+    mut queue: ResMut<PathQueue>,
+    grid: Res<NavGrid>,
+    clusters: Res<NavClusters>,
+    transitions: Res<Transitions>,
+    mut results: EventWriter<PathResult>,
+    mut snapshot: Local<Option<Arc<NavData>>>,
+) {
+    if grid.is_changed() || clusters.is_changed() || transitions.is_changed() {
+        *snapshot = None;
+    }
+    let count = queue.waiting.len().min(PATHS_PER_FRAME);
+    for request in queue.waiting.drain(..count).collect::<Vec<_>>() {
+        let data = snapshot
+            .get_or_insert_with(|| {
+                Arc::new(NavData {
+                    grid: grid.snapshot(),
+                    clusters: clusters.snapshot(),
+                    transitions: transitions.clone(),
+                })
+            })
+            .clone();
+        let entity = request.entity;
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let found = find_path(
+                request.start,
+                &request.goal,
+                &data.grid,
+                &data.clusters,
+                &data.transitions,
+            );
+            let (path, waypoints, partial) = match found {
+                Ok((path, waypoints, partial)) => (Ok(path), waypoints, partial),
+                Err(error) => (Err(error), None, None),
+            };
+            PathResult {
+                entity: request.entity,
+                goal: request.goal,
+                path,
+                waypoints,
+                partial,
+            }
+        });
+        queue.running.insert(entity, task);
+    }
+    queue
+        .running
+        .retain(|_, task| match block_on(future::poll_once(task)) {
+            Some(result) => {
+                results.send(result);
+                false
+            }
+            None => true,
+        });
+}
@@ -0,0 +1,250 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::PastCell;
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<RegionSet>()
+        .register_asset_loader(RegionSetLoader)
+        .init_resource::<Regions>()
+        .add_event::<RegionEntered>()
+        .add_event::<RegionExited>()
+        .add_systems(Update, track_regions);
+}
+
+/// The tiles a region covers, on every plane
+#[derive(Clone, Debug)]
+pub enum Shape {
+    /// tiles from `min` to `max` including both
+    Rect { min: IVec2, max: IVec2 },
+    /// tiles whose centre is inside the outline, the last point joins back to the first
+    Polygon(Vec<IVec2>),
+}
+
+impl Shape {
+    pub fn contains(&self, tile: IVec2) -> bool {
+        match self {
+            Shape::Rect { min, max } => tile.cmpge(*min).all() && tile.cmple(*max).all(),
+            Shape::Polygon(points) => {
+                // counts the edges a ray going east from the tile crosses, odd is inside
+                let point = tile.as_vec2();
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let (a, b) = (a.as_vec2(), points[(i + 1) % points.len()].as_vec2());
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// A named area of the map like a town or a stretch of coast
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub name: String,
+    pub shape: Shape,
+    /// players can attack each other here
+    pub pvp: bool,
+}
+
+/// Every region of the world, loaded from a `.regions.ron` file
+#[derive(Asset, TypePath)]
+pub struct RegionSet {
+    pub regions: Vec<Region>,
+}
+
+impl RegionSet {
+    /// the region a cell is in, where regions overlap the one first in the file wins
+    pub fn at(&self, cell: IVec3) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|region| region.shape.contains(cell.xz()))
+    }
+}
+
+/// the region set of the world
+#[derive(Resource)]
+pub struct Regions(pub Handle<RegionSet>);
+
+impl FromWorld for Regions {
+    fn from_world(world: &mut World) -> Self {
+        Regions(
+            world
+                .resource::<AssetServer>()
+                .load("regions/default.regions.ron"),
+        )
+    }
+}
+
+/// Finds the region of any cell, there are none until the region file has loaded
+#[derive(SystemParam)]
+pub struct RegionMap<'w> {
+    regions: Res<'w, Regions>,
+    sets: Res<'w, Assets<RegionSet>>,
+}
+
+impl RegionMap<'_> {
+    pub fn at(&self, cell: IVec3) -> Option<&Region> {
+        self.sets.get(&self.regions.0)?.at(cell)
+    }
+
+    pub fn named(&self, name: &str) -> Option<&Region> {
+        self.sets
+            .get(&self.regions.0)?
+            .regions
+            .iter()
+            .find(|region| region.name == name)
+    }
+}
+
+/// The name of the region an entity is in, kept up to date as its [`PastCell`] changes
+/// and when the region file loads
+#[derive(Component, Default, Debug)]
+pub struct InRegion(pub Option<String>);
+
+#[derive(Event, Clone, Debug)]
+pub struct RegionEntered {
+    pub entity: Entity,
+    pub region: String,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct RegionExited {
+    pub entity: Entity,
+    pub region: String,
+}
+
+fn track_regions(
+    mut commands: Commands,
+    mut movers: Query<(Entity, Ref<PastCell>, Option<&mut InRegion>)>,
+    regions: RegionMap,
+    mut events: EventReader<AssetEvent<RegionSet>>,
+    mut entered: EventWriter<RegionEntered>,
+    mut exited: EventWriter<RegionExited>,
+) {
+    // everyone is checked again when the regions load or change, even if they stand still
+    let id = regions.regions.0.id();
+    let reloaded = events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(id) || event.is_modified(id));
+    for (entity, past, in_region) in &mut movers {
+        if !reloaded && !past.is_changed() {
+            continue;
+        }
+        let region = regions.at(past.cell).map(|region| region.name.clone());
+        let old = match in_region {
+            Some(mut in_region) => {
+                if in_region.0 == region {
+                    continue;
+                }
+                std::mem::replace(&mut in_region.0, region.clone())
+            }
+            None => {
+                commands.entity(entity).insert(InRegion(region.clone()));
+                None
+            }
+        };
+        if let Some(region) = old {
+            exited.send(RegionExited { entity, region });
+        }
+        if let Some(region) = region {
+            entered.send(RegionEntered { entity, region });
+        }
+    }
+}
+
+/// `.regions.ron` file layout
+#[derive(Deserialize)]
+struct RegionSetFile {
+    regions: Vec<RegionFile>,
+}
+
+#[derive(Deserialize)]
+struct RegionFile {
+    name: String,
+    shape: ShapeFile,
+    #[serde(default)]
+    pvp: bool,
+}
+
+/// points are `(x, z)` tiles
+#[derive(Deserialize)]
+enum ShapeFile {
+    Rect { min: (i32, i32), max: (i32, i32) },
+    Polygon(Vec<(i32, i32)>),
+}
+
+#[derive(Debug, Error)]
+pub enum RegionSetLoadError {
+    #[error("could not read region file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse region file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("region {0} has an empty name")]
+    NoName(usize),
+    #[error("region `{0}` has a rect with min past max")]
+    Rect(String),
+    #[error("region `{0}` has a polygon with less than 3 points")]
+    Polygon(String),
+}
+
+#[derive(Default)]
+struct RegionSetLoader;
+
+impl AssetLoader for RegionSetLoader {
+    type Asset = RegionSet;
+    type Settings = ();
+    type Error = RegionSetLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<RegionSet, RegionSetLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: RegionSetFile = ron::de::from_bytes(&bytes)?;
+        let mut regions = Vec::with_capacity(file.regions.len());
+        for (index, region) in file.regions.into_iter().enumerate() {
+            if region.name.is_empty() {
+                return Err(RegionSetLoadError::NoName(index));
+            }
+            let shape = match region.shape {
+                ShapeFile::Rect { min, max } => {
+                    let (min, max) = (IVec2::from(min), IVec2::from(max));
+                    if min.cmpgt(max).any() {
+                        return Err(RegionSetLoadError::Rect(region.name));
+                    }
+                    Shape::Rect { min, max }
+                }
+                ShapeFile::Polygon(points) => {
+                    if points.len() < 3 {
+                        return Err(RegionSetLoadError::Polygon(region.name));
+                    }
+                    Shape::Polygon(points.into_iter().map(IVec2::from).collect())
+                }
+            };
+            regions.push(Region {
+                name: region.name,
+                shape,
+                pvp: region.pvp,
+            });
+        }
+        Ok(RegionSet { regions })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["regions.ron"]
+    }
+}
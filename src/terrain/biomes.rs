@@ -0,0 +1,281 @@
+use std::borrow::Cow;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    prelude::*,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::erosion::Rivers;
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<Biome>()
+        .init_asset::<BiomeRuleSet>()
+        .register_asset_loader(BiomeLoader)
+        .register_asset_loader(BiomeRuleSetLoader);
+    app.world_mut()
+        .resource_mut::<Assets<Biome>>()
+        .insert(&VOID, Biome::void());
+}
+
+/// Built in biome for tiles no rule matched, it is always loaded and can't be walked on
+pub const VOID: Handle<Biome> = Handle::weak_from_u128(0x5d1f_0a8c_3e7b_4c29_9a64_b2e1_07f3_c58d);
+/// magenta so unmatched tiles stand out
+pub const VOID_COLOR: Color = Color::srgb(1., 0., 1.);
+
+#[derive(Clone, Reflect, Asset)]
+pub struct Biome {
+    pub name: Cow<'static, str>,
+    pub move_cost: f32,
+    pub color: Color,
+    /// tiling detail texture, tinted by `color`
+    #[dependency]
+    pub texture: Option<Handle<Image>>,
+}
+
+impl Biome {
+    fn void() -> Biome {
+        Biome {
+            name: "Void".into(),
+            move_cost: f32::INFINITY,
+            color: VOID_COLOR,
+            texture: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BiomeRule {
+    pub priority: i8,
+    pub biome: Handle<Biome>,
+    pub min_hight: f32,
+    pub max_hight: f32,
+    pub min_temperature: f32,
+    pub max_temperature: f32,
+    pub min_moisture: f32,
+    pub max_moisture: f32,
+}
+
+/// The rules used to pick a biome for each tile, loaded from a `.rules.ron` file
+#[derive(Asset, TypePath, Clone)]
+pub struct BiomeRuleSet {
+    pub rules: Vec<BiomeRule>,
+    pub rivers: Option<Rivers>,
+    #[dependency]
+    biomes: Vec<Handle<Biome>>,
+}
+
+impl BiomeRuleSet {
+    /// every biome used by the rules and rivers
+    pub fn biomes(&self) -> &[Handle<Biome>] {
+        &self.biomes
+    }
+}
+
+/// `.biome.ron` file layout
+#[derive(Deserialize)]
+struct BiomeFile {
+    name: String,
+    /// hex colour `#RRGGBB`
+    color: String,
+    /// use `inf` for tiles that can't be walked on
+    move_cost: f32,
+    /// asset path of a tiling detail texture, all biome textures must be the same size
+    #[serde(default)]
+    texture: Option<String>,
+}
+
+/// `.rules.ron` file layout
+#[derive(Deserialize)]
+struct BiomeRuleSetFile {
+    rules: Vec<BiomeRuleFile>,
+    #[serde(default)]
+    rivers: Option<RiversFile>,
+}
+
+#[derive(Deserialize)]
+struct RiversFile {
+    /// asset path of the `.biome.ron` file for river tiles
+    biome: String,
+    /// asset path of the `.biome.ron` file rivers flow into
+    sea: String,
+    count: u32,
+}
+
+#[derive(Deserialize)]
+struct BiomeRuleFile {
+    /// asset path of a `.biome.ron` file
+    biome: String,
+    #[serde(default)]
+    priority: i8,
+    hight: (f32, f32),
+    temperature: (f32, f32),
+    #[serde(default = "any_moisture")]
+    moisture: (f32, f32),
+}
+
+fn any_moisture() -> (f32, f32) {
+    (0., 1.)
+}
+
+#[derive(Debug, Error)]
+pub enum BiomeLoadError {
+    #[error("could not read biome file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse biome file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("biome `{name}` has an invalid color `{color}`: {source}")]
+    Color {
+        name: String,
+        color: String,
+        source: bevy::color::HexColorError,
+    },
+    #[error("biome `{0}` has a negative or NaN move_cost")]
+    MoveCost(String),
+    #[error("biome has an empty name")]
+    NoName,
+}
+
+#[derive(Debug, Error)]
+pub enum BiomeRuleSetLoadError {
+    #[error("could not read rule set file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse rule set file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("rule set has no rules")]
+    Empty,
+    #[error("rule {index} ({biome}) has an invalid {field} range {min}..{max}, expected 0 <= min <= max <= 1")]
+    Range {
+        index: usize,
+        biome: String,
+        field: &'static str,
+        min: f32,
+        max: f32,
+    },
+}
+
+#[derive(Default)]
+struct BiomeLoader;
+
+impl AssetLoader for BiomeLoader {
+    type Asset = Biome;
+    type Settings = ();
+    type Error = BiomeLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Biome, BiomeLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: BiomeFile = ron::de::from_bytes(&bytes)?;
+        if file.name.is_empty() {
+            return Err(BiomeLoadError::NoName);
+        }
+        if file.move_cost.is_nan() || file.move_cost < 0. {
+            return Err(BiomeLoadError::MoveCost(file.name));
+        }
+        let color = match Srgba::hex(&file.color) {
+            Ok(color) => color,
+            Err(source) => {
+                return Err(BiomeLoadError::Color {
+                    name: file.name,
+                    color: file.color,
+                    source,
+                })
+            }
+        };
+        Ok(Biome {
+            name: file.name.into(),
+            move_cost: file.move_cost,
+            color: color.into(),
+            texture: file.texture.map(|texture| load_context.load(texture)),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["biome.ron"]
+    }
+}
+
+#[derive(Default)]
+struct BiomeRuleSetLoader;
+
+impl AssetLoader for BiomeRuleSetLoader {
+    type Asset = BiomeRuleSet;
+    type Settings = ();
+    type Error = BiomeRuleSetLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<BiomeRuleSet, BiomeRuleSetLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: BiomeRuleSetFile = ron::de::from_bytes(&bytes)?;
+        if file.rules.is_empty() {
+            return Err(BiomeRuleSetLoadError::Empty);
+        }
+        let mut rules = Vec::with_capacity(file.rules.len());
+        let mut biomes = Vec::new();
+        for (index, rule) in file.rules.into_iter().enumerate() {
+            for (field, (min, max)) in [
+                ("hight", rule.hight),
+                ("temperature", rule.temperature),
+                ("moisture", rule.moisture),
+            ] {
+                if !(0. ..=1.).contains(&min) || !(0. ..=1.).contains(&max) || min > max {
+                    return Err(BiomeRuleSetLoadError::Range {
+                        index,
+                        biome: rule.biome,
+                        field,
+                        min,
+                        max,
+                    });
+                }
+            }
+            // a missing or broken biome file fails this rule set through its dependencies
+            let biome: Handle<Biome> = load_context.load(rule.biome);
+            if !biomes.contains(&biome) {
+                biomes.push(biome.clone());
+            }
+            rules.push(BiomeRule {
+                priority: rule.priority,
+                biome,
+                min_hight: rule.hight.0,
+                max_hight: rule.hight.1,
+                min_temperature: rule.temperature.0,
+                max_temperature: rule.temperature.1,
+                min_moisture: rule.moisture.0,
+                max_moisture: rule.moisture.1,
+            });
+        }
+        let rivers = file.rivers.map(|rivers| {
+            let river = Rivers {
+                biome: load_context.load(rivers.biome),
+                sea: load_context.load(rivers.sea),
+                count: rivers.count,
+            };
+            for biome in [&river.biome, &river.sea] {
+                if !biomes.contains(biome) {
+                    biomes.push(biome.clone());
+                }
+            }
+            river
+        });
+        Ok(BiomeRuleSet {
+            rules,
+            rivers,
+            biomes,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rules.ron"]
+    }
+}
@@ -0,0 +1,609 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureFormat},
+        texture::ImageSampler,
+    },
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    fly_cam::FlyCam,
+    path_finding::{MoveCost, NavGrid, NavSystems, Occupancy, TileFactors},
+    ui::ContextActions,
+    Cell, CellIdToEntity, Player, Root,
+};
+
+use super::{
+    biomes::VOID_COLOR,
+    loading::{LoadingProgress, TerrainState},
+    material::{BiomeSplat, DetailTextures, TerrainMaterial},
+    objects::Objects,
+    scatter::{Scatter, ScatterSet},
+    Biome, BiomeCell, BiomeRuleSet, Biomes, Terrain, TerrainContext,
+};
+
+/// number of tiles along each side of a chunk
+pub const CHUNK_SIZE: isize = 40;
+/// how many chunks around a loader are kept spawned
+const VIEW_DISTANCE: i32 = 2;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<LoadedChunks>()
+        .add_event::<RedrawChunk>()
+        .add_event::<RefillNavGrid>()
+        .add_systems(
+            Update,
+            (
+                reload_biomes,
+                fill_nav_grid.before(NavSystems::Grid),
+                stream_chunks,
+                chunk_progress.run_if(in_state(TerrainState::Loading)),
+                redraw_chunks,
+                apply_occupancy,
+            )
+                .chain(),
+        );
+}
+
+/// Rebuilds a loaded chunk from the terrain after the maps under it changed
+#[derive(Event, Clone, Copy)]
+pub(super) struct RedrawChunk(pub Chunk);
+
+/// The cost of ground tiles changed with the biomes, the [`NavGrid`] is filled in again
+#[derive(Event)]
+struct RefillNavGrid;
+
+/// A square section of the terrain, the IVec2 is the chunk position in chunk space
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Chunk(pub IVec2);
+
+impl Chunk {
+    /// the chunk that contains a world position on a map `map_size` tiles across
+    pub fn from_world(pos: Vec3, map_size: isize) -> Chunk {
+        let x = pos.x.round() as isize + map_size / 2;
+        let z = pos.z.round() as isize + map_size / 2;
+        Chunk(IVec2::new(
+            x.div_euclid(CHUNK_SIZE) as i32,
+            z.div_euclid(CHUNK_SIZE) as i32,
+        ))
+    }
+
+    pub fn in_map(&self, map_size: isize) -> bool {
+        let chunks = (map_size / CHUNK_SIZE) as i32;
+        (0..chunks).contains(&self.0.x) && (0..chunks).contains(&self.0.y)
+    }
+
+    /// every tile in the chunk
+    pub(super) fn tiles(&self, map_size: isize) -> impl Iterator<Item = IVec3> {
+        let (x0, z0) = self.origin();
+        let half = map_size / 2;
+        (z0..z0 + CHUNK_SIZE).flat_map(move |z| {
+            (x0..x0 + CHUNK_SIZE).map(move |x| IVec3::new((x - half) as i32, 0, (z - half) as i32))
+        })
+    }
+
+    /// first tile of the chunk in map space (0..map size)
+    fn origin(&self) -> (isize, isize) {
+        (
+            self.0.x as isize * CHUNK_SIZE,
+            self.0.y as isize * CHUNK_SIZE,
+        )
+    }
+}
+
+/// the terrain the chunks were spawned from, its chunks and the chunks being built for it
+#[derive(Resource, Default)]
+struct LoadedChunks {
+    terrain: Option<Entity>,
+    chunks: HashMap<Chunk, Entity>,
+    building: HashMap<Chunk, Task<(Mesh, Image)>>,
+}
+
+/// vertices along each side of a chunk, the far edge is shared with the next chunk so there are no seams
+const VERTICES: isize = CHUNK_SIZE + 1;
+
+/// the texel each biome is drawn with, rgb is the biome color and alpha its detail layer
+type Palette = HashMap<AssetId<Biome>, [u8; 4]>;
+
+fn texel(color: Color, layer: u8) -> [u8; 4] {
+    let color = color.to_srgba();
+    [
+        (color.red * 255.) as u8,
+        (color.green * 255.) as u8,
+        (color.blue * 255.) as u8,
+        layer,
+    ]
+}
+
+fn make_palette(biomes: &Assets<Biome>, details: &DetailTextures) -> Palette {
+    biomes
+        .iter()
+        .map(|(id, biome)| (id, texel(biome.color, details.layer(id))))
+        .collect()
+}
+
+/// The part of the terrain a chunk is built from,
+/// copied out of the maps so the mesh and texture can be built on another thread
+struct ChunkSource {
+    chunk: Chunk,
+    map_size: isize,
+    /// world hights of the vertices and the ring of tiles around them the normals need,
+    /// tiles past the edge of the map use the edge tile
+    hights: Vec<f32>,
+    texels: Vec<[u8; 4]>,
+}
+
+impl Terrain {
+    fn chunk_source(&self, chunk: Chunk, palette: &Palette) -> ChunkSource {
+        let (x0, z0) = chunk.origin();
+        let size = self.size();
+        let index = |x: isize, z: isize| {
+            ((x0 + x).clamp(0, size - 1) + (z0 + z).clamp(0, size - 1) * size) as usize
+        };
+        let mut hights = Vec::with_capacity(((VERTICES + 2) * (VERTICES + 2)) as usize);
+        for z in -1..=VERTICES {
+            for x in -1..=VERTICES {
+                hights.push(self.hight_map[index(x, z)] * self.settings.hight_scale);
+            }
+        }
+        let mut texels = Vec::with_capacity((VERTICES * VERTICES) as usize);
+        for z in 0..VERTICES {
+            for x in 0..VERTICES {
+                let biome = self.biome_map[index(x, z)].id();
+                // biomes that aren't loaded are drawn as Void, like their cells
+                texels.push(
+                    palette
+                        .get(&biome)
+                        .copied()
+                        .unwrap_or_else(|| texel(VOID_COLOR, 0)),
+                );
+            }
+        }
+        ChunkSource {
+            chunk,
+            map_size: size,
+            hights,
+            texels,
+        }
+    }
+}
+
+impl ChunkSource {
+    /// hight of a vertex, -1 and `VERTICES` are the ring around the chunk
+    fn hight(&self, x: isize, z: isize) -> f32 {
+        self.hights[(x + 1 + (z + 1) * (VERTICES + 2)) as usize]
+    }
+
+    /// the mesh shares its far edge with the next chunk so there are no seams
+    fn mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new(
+            bevy::render::mesh::PrimitiveTopology::TriangleList,
+            RenderAssetUsages::all(),
+        );
+        let (x0, z0) = self.chunk.origin();
+        let half = self.map_size / 2;
+        let width = VERTICES.min(self.map_size - x0);
+        let depth = VERTICES.min(self.map_size - z0);
+        let mut points = Vec::new();
+        let mut indices = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        for z in 0..depth {
+            for x in 0..width {
+                let index = (x + z * width) as u32;
+                points.push([
+                    (x0 + x - half) as f32,
+                    self.hight(x, z),
+                    (z0 + z - half) as f32,
+                ]);
+                // central differences so normals match across chunk edges
+                normals.push(
+                    Vec3::new(
+                        self.hight(x - 1, z) - self.hight(x + 1, z),
+                        2.,
+                        self.hight(x, z - 1) - self.hight(x, z + 1),
+                    )
+                    .normalize()
+                    .to_array(),
+                );
+                // texel centres line up with vertices so each tile is one colour
+                uvs.push([
+                    (x as f32 + 0.5) / VERTICES as f32,
+                    (z as f32 + 0.5) / VERTICES as f32,
+                ]);
+                if x == width - 1 || z == depth - 1 {
+                    continue;
+                }
+                indices.extend_from_slice(&[
+                    index + 1,
+                    index + width as u32,
+                    index + width as u32 + 1,
+                    index,
+                    index + width as u32,
+                    index + 1,
+                ]);
+            }
+        }
+        mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+
+        mesh
+    }
+
+    /// the biome texture, one texel per vertex of [`ChunkSource::mesh`]
+    fn texture(&self) -> Image {
+        let size = Extent3d {
+            width: VERTICES as u32,
+            height: VERTICES as u32,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image::new(
+            size,
+            bevy::render::render_resource::TextureDimension::D2,
+            self.texels.concat(),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::all(),
+        );
+        image.sampler = ImageSampler::nearest();
+        image
+    }
+}
+
+/// builds chunks near the player and camera in the background, spawns them once they are built
+/// and despawns the ones that are out of range
+fn stream_chunks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    terrains: Query<(Entity, &Terrain)>,
+    loaders: Query<&GlobalTransform, Or<(With<Player>, With<FlyCam>)>>,
+    biomes: Res<Assets<Biome>>,
+    details: Res<DetailTextures>,
+    context: Res<TerrainContext>,
+    occupancy: Res<Occupancy>,
+    mut loaded: ResMut<LoadedChunks>,
+) {
+    let Ok((terrain_entity, terrain)) = terrains.get_single() else {
+        return;
+    };
+    if !details.ready() {
+        return;
+    }
+    if loaded.terrain != Some(terrain_entity) {
+        // the old chunks were despawned with their terrain
+        loaded.terrain = Some(terrain_entity);
+        loaded.chunks.clear();
+        loaded.building.clear();
+    }
+    let mut wanted = HashSet::new();
+    for loader in &loaders {
+        let center = Chunk::from_world(loader.translation(), terrain.size());
+        for z in -VIEW_DISTANCE..=VIEW_DISTANCE {
+            for x in -VIEW_DISTANCE..=VIEW_DISTANCE {
+                let chunk = Chunk(center.0 + IVec2::new(x, z));
+                if chunk.in_map(terrain.size()) {
+                    wanted.insert(chunk);
+                }
+            }
+        }
+    }
+
+    loaded.chunks.retain(|chunk, entity| {
+        if wanted.contains(chunk) {
+            true
+        } else {
+            commands.entity(*entity).despawn_recursive();
+            false
+        }
+    });
+    // dropping a task only stops it if it hasn't started, a chunk already being built
+    // is finished in the background and thrown away
+    loaded.building.retain(|chunk, _| wanted.contains(chunk));
+
+    let mut palette = None;
+    for chunk in wanted {
+        if loaded.chunks.contains_key(&chunk) || loaded.building.contains_key(&chunk) {
+            continue;
+        }
+        let palette = palette.get_or_insert_with(|| make_palette(&biomes, &details));
+        let source = terrain.chunk_source(chunk, palette);
+        let task =
+            AsyncComputeTaskPool::get().spawn(async move { (source.mesh(), source.texture()) });
+        loaded.building.insert(chunk, task);
+    }
+
+    let built = loaded
+        .building
+        .iter_mut()
+        .filter_map(|(chunk, task)| Some((*chunk, block_on(future::poll_once(task))?)))
+        .collect::<Vec<_>>();
+    for (chunk, (mesh, texture)) in built {
+        loaded.building.remove(&chunk);
+        let texture = asset_server.add(texture);
+        let (x0, z0) = chunk.origin();
+        let (size, half) = (terrain.size(), terrain.half_size());
+        let entity = commands
+            .spawn((
+                chunk,
+                Name::new("Terrain"),
+                Root,
+                Transform::default(),
+                Visibility::default(),
+                Mesh3d(asset_server.add(mesh)),
+                MeshMaterial3d(asset_server.add(TerrainMaterial {
+                    base: StandardMaterial {
+                        base_color: Color::WHITE,
+                        perceptual_roughness: 0.9,
+                        reflectance: 0.2,
+                        ..Default::default()
+                    },
+                    extension: BiomeSplat {
+                        biomes: texture,
+                        detail: details.array.clone(),
+                    },
+                })),
+                ContextActions {
+                    on_open: Some(context.on_open),
+                    options: vec![("Walk".into(), context.walk)],
+                    on_close: None,
+                },
+            ))
+            .with_children(|commands| {
+                for z in z0..z0 + CHUNK_SIZE {
+                    for x in x0..x0 + CHUNK_SIZE {
+                        let index = (x + z * size) as usize;
+                        let hight = terrain.hight_map[index];
+                        let biome_handle = &terrain.biome_map[index];
+                        // every tile gets a cell so they line up with the texture,
+                        // biomes that aren't loaded can't be walked on like Void
+                        let move_cost = biomes
+                            .get(biome_handle)
+                            .map_or(f32::INFINITY, |biome| biome.move_cost);
+                        let tile = IVec3::new((x - half) as i32, 0, (z - half) as i32);
+
+                        commands.spawn((
+                            BiomeCell(biome_handle.clone()),
+                            Transform::from_translation(Vec3::new(
+                                (x - half) as f32,
+                                hight * terrain.settings.hight_scale,
+                                (z - half) as f32,
+                            )),
+                            Cell,
+                            MoveCost(occupancy.cost(tile, move_cost)),
+                        ));
+                    }
+                }
+            })
+            .set_parent(terrain_entity)
+            .id();
+        loaded.chunks.insert(chunk, entity);
+    }
+}
+
+/// shows how many of the chunks around the loaders are built, loading is done once they all are
+fn chunk_progress(
+    terrains: Query<Entity, With<Terrain>>,
+    loaded: Res<LoadedChunks>,
+    progress: Res<LoadingProgress>,
+    mut state: ResMut<NextState<TerrainState>>,
+) {
+    let Ok(terrain) = terrains.get_single() else {
+        return;
+    };
+    if loaded.terrain != Some(terrain) {
+        return;
+    }
+    let built = loaded.chunks.len();
+    let total = built + loaded.building.len();
+    progress
+        .0
+        .set("Building chunks", built as f32 / total.max(1) as f32);
+    if loaded.building.is_empty() && built > 0 {
+        state.set(TerrainState::Ready);
+    }
+}
+
+/// regenerates the biome map and redraws the chunks when biome or rule files change on disk
+/// or a biome the rules use finishes loading
+fn reload_biomes(
+    mut biome_events: EventReader<AssetEvent<Biome>>,
+    mut rule_events: EventReader<AssetEvent<BiomeRuleSet>>,
+    rule_set: Res<Biomes>,
+    rule_sets: Res<Assets<BiomeRuleSet>>,
+    mut terrains: Query<&mut Terrain>,
+    loaded: Res<LoadedChunks>,
+    mut redraw: EventWriter<RedrawChunk>,
+    mut refill: EventWriter<RefillNavGrid>,
+) {
+    let rules_changed = rule_events
+        .read()
+        .any(|event| event.is_modified(rule_set.0.id()));
+    let used = rule_sets
+        .get(&rule_set.0)
+        .map_or(&[][..], |rules| rules.biomes());
+    let biomes_changed = biome_events.read().any(|event| match event {
+        AssetEvent::Modified { .. } => true,
+        // a biome new to the rules loads after the rules, its tiles were drawn as Void
+        AssetEvent::Added { id } | AssetEvent::LoadedWithDependencies { id } => {
+            used.iter().any(|biome| biome.id() == *id)
+        }
+        _ => false,
+    });
+    if !rules_changed && !biomes_changed {
+        return;
+    }
+    let Ok(mut terrain) = terrains.get_single_mut() else {
+        return;
+    };
+    if rules_changed {
+        let Some(rule_set) = rule_sets.get(&rule_set.0) else {
+            warn!("Biome rules changed but are not loaded");
+            return;
+        };
+        info!("Biome rules changed, regenerating biome map");
+        terrain.apply_rules(rule_set);
+    }
+    refill.send(RefillNavGrid);
+    redraw.send_batch(
+        loaded
+            .chunks
+            .keys()
+            .chain(loaded.building.keys())
+            .map(|chunk| RedrawChunk(*chunk)),
+    );
+}
+
+/// updates the mesh, texture and cells of redrawn chunks, chunks that aren't loaded are skipped
+/// since they are built from the current maps when they load,
+/// chunks that are being built are started again so they don't use the old maps,
+/// the old build still finishes in the background and is thrown away
+fn redraw_chunks(
+    mut events: EventReader<RedrawChunk>,
+    terrains: Query<&Terrain>,
+    mut loaded: ResMut<LoadedChunks>,
+    chunks: Query<(&Mesh3d, &MeshMaterial3d<TerrainMaterial>, &Children)>,
+    mut cells: Query<(&mut Transform, &mut BiomeCell, &mut MoveCost)>,
+    biomes: Res<Assets<Biome>>,
+    details: Res<DetailTextures>,
+    occupancy: Res<Occupancy>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let redraw = events.read().map(|event| event.0).collect::<HashSet<_>>();
+    let Ok(terrain) = terrains.get_single() else {
+        return;
+    };
+    let palette = make_palette(&biomes, &details);
+    for chunk in redraw {
+        loaded.building.remove(&chunk);
+        let Some(entity) = loaded.chunks.get(&chunk) else {
+            continue;
+        };
+        let Ok((mesh, material, children)) = chunks.get(*entity) else {
+            continue;
+        };
+        // edits need to show straight away so these are built here
+        let source = terrain.chunk_source(chunk, &palette);
+        meshes.insert(&mesh.0, source.mesh());
+        // get_mut so the material rebinds the new texture
+        if let Some(material) = materials.get_mut(&material.0) {
+            images.insert(&material.extension.biomes, source.texture());
+        }
+        for child in children {
+            let Ok((mut pos, mut cell, mut cost)) = cells.get_mut(*child) else {
+                continue;
+            };
+            let tile = pos.translation.round().as_ivec3();
+            let (Some(hight), Some(handle)) = (terrain.tile_hight(tile), terrain.tile_biome(tile))
+            else {
+                continue;
+            };
+            if pos.translation.y != hight {
+                pos.translation.y = hight;
+            }
+            if cell.0 != *handle {
+                cell.0 = handle.clone();
+            }
+            let move_cost = biomes
+                .get(handle)
+                .map_or(f32::INFINITY, |biome| biome.move_cost);
+            cost.0 = occupancy.cost(tile.with_y(0), move_cost);
+        }
+    }
+}
+
+/// Gives the nav grid the cost of ground tiles from their biome and the objects scattered on them,
+/// so paths go around objects in chunks that aren't loaded
+#[derive(SystemParam)]
+pub(super) struct GroundCosts<'w> {
+    scatter: Res<'w, Scatter>,
+    scatter_sets: Res<'w, Assets<ScatterSet>>,
+    biomes: Res<'w, Assets<Biome>>,
+    grid: ResMut<'w, NavGrid>,
+}
+
+impl GroundCosts<'_> {
+    /// every ground tile of the terrain
+    fn fill(&mut self, terrain: &Terrain, objects: &Objects) {
+        // the same stacked factors the occupancy gives objects once their chunk spawns
+        let factors = self
+            .scatter_sets
+            .get(&self.scatter.0)
+            .map(|scatter| objects.factors(scatter))
+            .unwrap_or_default();
+        for index in 0..terrain.biome_map.len() {
+            self.set(terrain, &factors, index);
+        }
+    }
+
+    /// only the tiles at some indices into the terrain maps, like the ones an edit changed
+    pub fn update(&mut self, terrain: &Terrain, objects: &Objects, indices: &[usize]) {
+        let tiles = indices.iter().map(|index| terrain.index_tile(*index));
+        let factors = self
+            .scatter_sets
+            .get(&self.scatter.0)
+            .map(|scatter| objects.factors_on(scatter, tiles))
+            .unwrap_or_default();
+        for index in indices {
+            self.set(terrain, &factors, *index);
+        }
+    }
+
+    fn set(&mut self, terrain: &Terrain, factors: &TileFactors, index: usize) {
+        let move_cost = self
+            .biomes
+            .get(&terrain.biome_map[index])
+            .map_or(f32::INFINITY, |biome| biome.move_cost);
+        let tile = terrain.index_tile(index);
+        self.grid.set_cost(tile, factors.cost(tile, move_cost));
+    }
+}
+
+fn fill_nav_grid(
+    terrains: Query<(Ref<Terrain>, &Objects)>,
+    mut refill: EventReader<RefillNavGrid>,
+    mut costs: GroundCosts,
+) {
+    let refill = refill.read().count() > 0;
+    let Ok((terrain, objects)) = terrains.get_single() else {
+        return;
+    };
+    if terrain.is_added() {
+        let (min, size) = terrain.bounds();
+        costs.grid.resize(min, size);
+    } else if !refill {
+        return;
+    }
+    costs.fill(&terrain, objects);
+}
+
+/// updates the cost of cells objects were placed on or removed from
+fn apply_occupancy(
+    mut occupancy: ResMut<Occupancy>,
+    cell_map: Res<CellIdToEntity>,
+    mut cells: Query<(Option<&BiomeCell>, &mut MoveCost)>,
+    biomes: Res<Assets<Biome>>,
+) {
+    for tile in occupancy.take_changed() {
+        // cells that aren't loaded get the occupancy when their chunk spawns
+        let Some(entity) = cell_map.get_by_id(&tile) else {
+            continue;
+        };
+        let Ok((biome, mut cost)) = cells.get_mut(entity) else {
+            continue;
+        };
+        let move_cost = match biome {
+            Some(biome) => biomes
+                .get(&biome.0)
+                .map_or(f32::INFINITY, |biome| biome.move_cost),
+            None => MoveCost::default().0,
+        };
+        cost.0 = occupancy.cost(tile, move_cost);
+    }
+}
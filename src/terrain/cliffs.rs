@@ -0,0 +1,68 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::collision::{Blocker, Edges, Side};
+
+use super::{chunks::RedrawChunk, Chunk, Terrain};
+
+/// hight difference in world units between neighbouring tiles that is too steep to walk
+const CLIFF_HIGHT: f32 = 1.5;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Update, (add_cliffs, update_cliffs));
+}
+
+/// the north and east edges of the tiles that are too steep to cross,
+/// the south and west edges are the north and east edges of the next tiles
+fn cliff_edges(terrain: &Terrain, tiles: impl Iterator<Item = IVec3>) -> Vec<(IVec3, Edges)> {
+    let mut cliffs = Vec::new();
+    for tile in tiles {
+        let Some(hight) = terrain.tile_hight(tile) else {
+            continue;
+        };
+        let mut edges = Edges::NONE;
+        for side in [Side::North, Side::East] {
+            let Some(next) = terrain.tile_hight(tile + side.offset()) else {
+                continue;
+            };
+            if (hight - next).abs() > CLIFF_HIGHT {
+                edges |= Edges::fence(side);
+            }
+        }
+        if edges != Edges::NONE {
+            cliffs.push((tile, edges));
+        }
+    }
+    cliffs
+}
+
+/// blocks the cliff edges of new terrain, the blocker lives on the terrain entity
+fn add_cliffs(mut commands: Commands, terrains: Query<(Entity, &Terrain), Added<Terrain>>) {
+    for (entity, terrain) in &terrains {
+        let tiles = (0..terrain.hight_map.len()).map(|index| terrain.index_tile(index));
+        commands
+            .entity(entity)
+            .insert(Blocker(cliff_edges(terrain, tiles)));
+    }
+}
+
+/// finds the cliffs again in chunks whose hights were edited
+fn update_cliffs(
+    mut events: EventReader<RedrawChunk>,
+    mut terrains: Query<(&Terrain, &mut Blocker)>,
+) {
+    let redraw = events.read().map(|event| event.0).collect::<HashSet<_>>();
+    if redraw.is_empty() {
+        return;
+    }
+    let Ok((terrain, mut blocker)) = terrains.get_single_mut() else {
+        return;
+    };
+    let in_redraw =
+        |tile: &IVec3| redraw.contains(&Chunk::from_world(tile.as_vec3(), terrain.size()));
+    blocker.0.retain(|(tile, _)| !in_redraw(tile));
+    let tiles = redraw
+        .iter()
+        .flat_map(|chunk| chunk.tiles(terrain.size()))
+        .collect::<Vec<_>>();
+    blocker.0.extend(cliff_edges(terrain, tiles.into_iter()));
+}
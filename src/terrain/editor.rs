@@ -0,0 +1,345 @@
+use bevy::{
+    input::common_conditions::input_just_pressed,
+    picking::{focus::HoverMap, pointer::PointerId},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunks::{GroundCosts, RedrawChunk},
+    objects::Objects,
+    Biome, BiomeRuleSet, Biomes, Chunk, Terrain,
+};
+
+/// how many strokes can be undone
+const UNDO_LIMIT: usize = 50;
+/// hight change per second under the centre of a full strength raise or lower brush
+const SCULPT_SPEED: f32 = 0.2;
+/// how fast the flatten brush pulls tiles to its level
+const FLATTEN_SPEED: f32 = 5.;
+const MAX_RADIUS: f32 = 50.;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Editor>()
+        .add_systems(Startup, spawn_editor_text)
+        .add_systems(
+            Update,
+            (
+                toggle_editor.run_if(input_just_pressed(KeyCode::Tab)),
+                (
+                    editor_keys,
+                    undo.run_if(input_just_pressed(KeyCode::KeyZ)),
+                    sculpt,
+                )
+                    .chain()
+                    .run_if(editing),
+                update_editor_text,
+            )
+                .chain(),
+        );
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Brush {
+    Raise,
+    Lower,
+    /// levels the tiles to the hight where the stroke started
+    Flatten,
+    Paint,
+}
+
+/// Sculpts and paints the terrain with the left mouse button, toggled with Tab.
+/// Edits change the terrain maps so they are saved with the world.
+#[derive(Resource)]
+pub struct Editor {
+    pub active: bool,
+    pub brush: Brush,
+    /// in tiles
+    pub radius: f32,
+    /// 0..1
+    pub strength: f32,
+    /// index into the biomes of the rule set, used by the paint brush
+    pub biome: usize,
+    stroke: Option<Stroke>,
+    history: Vec<Stroke>,
+    /// the terrain `history` belongs to
+    terrain: Option<Entity>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor {
+            active: false,
+            brush: Brush::Raise,
+            radius: 5.,
+            strength: 0.5,
+            biome: 0,
+            stroke: None,
+            history: Vec::new(),
+            terrain: None,
+        }
+    }
+}
+
+impl Editor {
+    /// keeps the stroke being drawn so it can be undone, the oldest stroke goes past the limit
+    fn finish_stroke(&mut self) {
+        let Some(stroke) = self.stroke.take() else {
+            return;
+        };
+        if stroke.before.is_empty() {
+            return;
+        }
+        self.history.push(stroke);
+        if self.history.len() > UNDO_LIMIT {
+            self.history.remove(0);
+        }
+    }
+}
+
+/// the tiles changed while the mouse was held and what they were before
+#[derive(Default)]
+struct Stroke {
+    /// hight the flatten brush levels to
+    level: Option<f32>,
+    before: HashMap<usize, (f32, Handle<Biome>)>,
+}
+
+/// run condition for systems that shouldn't run while editing the terrain
+pub fn editing(editor: Res<Editor>) -> bool {
+    editor.active
+}
+
+fn toggle_editor(mut editor: ResMut<Editor>) {
+    editor.active = !editor.active;
+    // finish any stroke so it can still be undone
+    editor.finish_stroke();
+}
+
+fn editor_keys(input: Res<ButtonInput<KeyCode>>, mut editor: ResMut<Editor>) {
+    for (key, brush) in [
+        (KeyCode::Digit1, Brush::Raise),
+        (KeyCode::Digit2, Brush::Lower),
+        (KeyCode::Digit3, Brush::Flatten),
+        (KeyCode::Digit4, Brush::Paint),
+    ] {
+        if input.just_pressed(key) {
+            editor.brush = brush;
+        }
+    }
+    if input.just_pressed(KeyCode::BracketLeft) {
+        editor.radius = (editor.radius - 1.).max(1.);
+    }
+    if input.just_pressed(KeyCode::BracketRight) {
+        editor.radius = (editor.radius + 1.).min(MAX_RADIUS);
+    }
+    if input.just_pressed(KeyCode::Minus) {
+        editor.strength = (editor.strength - 0.1).max(0.1);
+    }
+    if input.just_pressed(KeyCode::Equal) {
+        editor.strength = (editor.strength + 0.1).min(1.);
+    }
+    if input.just_pressed(KeyCode::KeyB) {
+        editor.biome = editor.biome.wrapping_add(1);
+    }
+}
+
+/// the biome the paint brush uses
+fn paint_biome(editor: &Editor, rule_set: &BiomeRuleSet) -> Option<Handle<Biome>> {
+    let biomes = rule_set.biomes();
+    if biomes.is_empty() {
+        return None;
+    }
+    Some(biomes[editor.biome % biomes.len()].clone())
+}
+
+/// the chunks whose mesh uses a tile, the mesh normals reach one tile into the next chunk
+fn chunks_around(terrain: &Terrain, tile: IVec3, chunks: &mut HashSet<Chunk>) {
+    for z in -1..=1 {
+        for x in -1..=1 {
+            let chunk = Chunk::from_world((tile + IVec3::new(x, 0, z)).as_vec3(), terrain.size());
+            if chunk.in_map(terrain.size()) {
+                chunks.insert(chunk);
+            }
+        }
+    }
+}
+
+fn sculpt(
+    mouse: Res<ButtonInput<MouseButton>>,
+    hover: Res<HoverMap>,
+    chunks: Query<(), With<Chunk>>,
+    mut terrains: Query<(Entity, &mut Terrain, &Objects)>,
+    biomes: Res<Biomes>,
+    rule_sets: Res<Assets<BiomeRuleSet>>,
+    time: Res<Time>,
+    mut editor: ResMut<Editor>,
+    mut redraw: EventWriter<RedrawChunk>,
+    mut costs: GroundCosts,
+) {
+    let Ok((entity, mut terrain, objects)) = terrains.get_single_mut() else {
+        return;
+    };
+    if editor.terrain != Some(entity) {
+        // a new or loaded terrain, the old edits don't apply to it
+        editor.terrain = Some(entity);
+        editor.history.clear();
+        editor.stroke = None;
+    }
+    if !mouse.pressed(MouseButton::Left) {
+        editor.finish_stroke();
+        return;
+    }
+    let Some(centre) = hover
+        .get(&PointerId::Mouse)
+        .and_then(|hits| hits.iter().find(|(entity, _)| chunks.contains(**entity)))
+        .and_then(|(_, hit)| hit.position)
+    else {
+        return;
+    };
+    let centre = centre.xz();
+    let paint = match editor.brush {
+        Brush::Paint => {
+            let Some(biome) = rule_sets
+                .get(&biomes.0)
+                .and_then(|rule_set| paint_biome(&editor, rule_set))
+            else {
+                return;
+            };
+            Some(biome)
+        }
+        _ => None,
+    };
+    let (brush, radius) = (editor.brush, editor.radius);
+    let rate = editor.strength * time.delta_secs();
+    let hight_scale = terrain.settings.hight_scale;
+    let stroke = editor.stroke.get_or_insert_with(Stroke::default);
+    let level = *stroke
+        .level
+        .get_or_insert_with(|| terrain.hight_at(centre).unwrap_or_default() / hight_scale);
+
+    let mut dirty = HashSet::new();
+    let mut edited = Vec::new();
+    let min = (centre - radius).floor().as_ivec2();
+    let max = (centre + radius).ceil().as_ivec2();
+    for z in min.y..=max.y {
+        for x in min.x..=max.x {
+            let distance = Vec2::new(x as f32, z as f32).distance(centre);
+            if distance > radius {
+                continue;
+            }
+            let tile = IVec3::new(x, 0, z);
+            let Some(index) = terrain.tile_index(tile) else {
+                continue;
+            };
+            // smooth falloff so brushes don't leave steps at their edge
+            let falloff = 1. - distance / radius;
+            let weight = falloff * falloff * (3. - 2. * falloff);
+            stroke
+                .before
+                .entry(index)
+                .or_insert_with(|| (terrain.hight_map[index], terrain.biome_map[index].clone()));
+            let hight = terrain.hight_map[index];
+            match (brush, &paint) {
+                (Brush::Raise, _) => {
+                    terrain.hight_map[index] = (hight + SCULPT_SPEED * weight * rate).min(1.)
+                }
+                (Brush::Lower, _) => {
+                    terrain.hight_map[index] = (hight - SCULPT_SPEED * weight * rate).max(0.)
+                }
+                (Brush::Flatten, _) => {
+                    terrain.hight_map[index] =
+                        hight.lerp(level, (FLATTEN_SPEED * weight * rate).min(1.))
+                }
+                (Brush::Paint, Some(paint)) => terrain.biome_map[index] = paint.clone(),
+                (Brush::Paint, None) => {}
+            }
+            edited.push(index);
+            chunks_around(&terrain, tile, &mut dirty);
+        }
+    }
+    // only the biome changes the cost, chunks that aren't loaded don't redraw to pass it on
+    if paint.is_some() {
+        costs.update(&terrain, objects, &edited);
+    }
+    redraw.send_batch(dirty.into_iter().map(RedrawChunk));
+}
+
+fn undo(
+    input: Res<ButtonInput<KeyCode>>,
+    mut terrains: Query<(&mut Terrain, &Objects)>,
+    mut editor: ResMut<Editor>,
+    mut redraw: EventWriter<RedrawChunk>,
+    mut costs: GroundCosts,
+) {
+    if !input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let Ok((mut terrain, objects)) = terrains.get_single_mut() else {
+        return;
+    };
+    let Some(stroke) = editor.history.pop() else {
+        info!("Nothing to undo");
+        return;
+    };
+    let mut dirty = HashSet::new();
+    let mut edited = Vec::with_capacity(stroke.before.len());
+    for (index, (hight, biome)) in stroke.before {
+        terrain.hight_map[index] = hight;
+        terrain.biome_map[index] = biome;
+        let tile = terrain.index_tile(index);
+        chunks_around(&terrain, tile, &mut dirty);
+        edited.push(index);
+    }
+    costs.update(&terrain, objects, &edited);
+    redraw.send_batch(dirty.into_iter().map(RedrawChunk));
+}
+
+#[derive(Component)]
+struct EditorText;
+
+fn spawn_editor_text(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            right: Val::Px(10.),
+            ..Default::default()
+        },
+        Visibility::Hidden,
+        EditorText,
+    ));
+}
+
+fn update_editor_text(
+    editor: Res<Editor>,
+    biomes: Res<Biomes>,
+    rule_sets: Res<Assets<BiomeRuleSet>>,
+    biome_assets: Res<Assets<Biome>>,
+    mut text: Query<(&mut Text, &mut Visibility), With<EditorText>>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    let Ok((mut text, mut visibility)) = text.get_single_mut() else {
+        return;
+    };
+    if !editor.active {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+    let biome = rule_sets
+        .get(&biomes.0)
+        .and_then(|rule_set| paint_biome(&editor, rule_set))
+        .and_then(|biome| biome_assets.get(&biome))
+        .map(|biome| biome.name.to_string())
+        .unwrap_or_default();
+    text.0 = format!(
+        "Editing terrain\n\
+        {:?} brush, radius {}, strength {:.1}, paint {biome}\n\
+        1-4 brush, [ ] radius, - = strength, B biome, Ctrl+Z undo, Tab to stop",
+        editor.brush, editor.radius, editor.strength
+    );
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::Biome;
+
+/// raindrops simulated per tile of the map
+const DROPLETS_PER_TILE: f32 = 0.1;
+/// max steps a raindrop takes before it evaporates
+const LIFETIME: usize = 30;
+/// how much a drop keeps its direction instead of following the slope
+const INERTIA: f32 = 0.05;
+const CAPACITY: f32 = 4.;
+const MIN_CAPACITY: f32 = 0.01;
+const DEPOSIT_SPEED: f32 = 0.3;
+const ERODE_SPEED: f32 = 0.3;
+const EVAPORATE_SPEED: f32 = 0.01;
+const GRAVITY: f32 = 4.;
+
+/// max tiles a river can flow before it is given up on
+const MAX_RIVER_LENGTH: usize = 2000;
+/// how far a river sinks below the land around it
+const RIVER_DEPTH: f32 = 0.01;
+/// rivers only start above this hight
+const SOURCE_HIGHT: f32 = 0.6;
+
+const NEIGHBORS: [(isize, isize); 8] = [
+    (0, 1),
+    (-1, 0),
+    (0, -1),
+    (1, 0),
+    (-1, 1),
+    (-1, -1),
+    (1, -1),
+    (1, 1),
+];
+
+/// Rivers carved into the terrain after the biomes are picked
+#[derive(Clone)]
+pub struct Rivers {
+    /// the biome of the river tiles
+    pub biome: Handle<Biome>,
+    /// the biome rivers flow into, a river that never reaches it is dropped
+    pub sea: Handle<Biome>,
+    pub count: u32,
+}
+
+/// hight and gradient at a point between tiles
+fn hight_and_gradient(map: &[f32], size: usize, x: f32, z: f32) -> (f32, Vec2) {
+    let cx = x as usize;
+    let cz = z as usize;
+    let u = x - cx as f32;
+    let v = z - cz as f32;
+    let index = cx + cz * size;
+    let nw = map[index];
+    let ne = map[index + 1];
+    let sw = map[index + size];
+    let se = map[index + size + 1];
+    let gradient = Vec2::new(
+        (ne - nw) * (1. - v) + (se - sw) * v,
+        (sw - nw) * (1. - u) + (se - ne) * u,
+    );
+    let hight = nw * (1. - u) * (1. - v) + ne * u * (1. - v) + sw * (1. - u) * v + se * u * v;
+    (hight, gradient)
+}
+
+/// spreads a change in hight over the four tiles around a point
+fn add_hight(map: &mut [f32], size: usize, x: f32, z: f32, amount: f32) {
+    let cx = x as usize;
+    let cz = z as usize;
+    let u = x - cx as f32;
+    let v = z - cz as f32;
+    let index = cx + cz * size;
+    map[index] += amount * (1. - u) * (1. - v);
+    map[index + 1] += amount * u * (1. - v);
+    map[index + size] += amount * (1. - u) * v;
+    map[index + size + 1] += amount * u * v;
+}
+
+/// Simulates rain washing soil down hills, the same random numbers always erode the same way
+pub fn erode(hight_map: &mut [f32], size: usize, mut rng: impl Rng) {
+    let max = (size - 1) as f32;
+    let droplets = (hight_map.len() as f32 * DROPLETS_PER_TILE) as usize;
+    for _ in 0..droplets {
+        let mut x = rng.gen_range(0. ..max);
+        let mut z = rng.gen_range(0. ..max);
+        let mut direction = Vec2::ZERO;
+        let mut speed = 1.;
+        let mut water = 1.;
+        let mut sediment = 0.;
+        for _ in 0..LIFETIME {
+            let (hight, gradient) = hight_and_gradient(hight_map, size, x, z);
+            direction = (direction * INERTIA - gradient * (1. - INERTIA)).normalize_or_zero();
+            if direction == Vec2::ZERO {
+                break;
+            }
+            let (old_x, old_z) = (x, z);
+            x += direction.x;
+            z += direction.y;
+            if !(0. ..max).contains(&x) || !(0. ..max).contains(&z) {
+                break;
+            }
+            let delta = hight_and_gradient(hight_map, size, x, z).0 - hight;
+            let capacity = (-delta * speed * water * CAPACITY).max(MIN_CAPACITY);
+            if sediment > capacity || delta > 0. {
+                let deposit = if delta > 0. {
+                    delta.min(sediment)
+                } else {
+                    (sediment - capacity) * DEPOSIT_SPEED
+                };
+                sediment -= deposit;
+                add_hight(hight_map, size, old_x, old_z, deposit);
+            } else {
+                let erode = ((capacity - sediment) * ERODE_SPEED).min(-delta);
+                sediment += erode;
+                add_hight(hight_map, size, old_x, old_z, -erode);
+            }
+            speed = (speed * speed + delta * GRAVITY).max(0.).sqrt();
+            water *= 1. - EVAPORATE_SPEED;
+        }
+    }
+    for hight in hight_map {
+        *hight = hight.clamp(0., 1.);
+    }
+}
+
+/// Flows rivers from high ground down to the sea, lowering the land under them.
+/// Each step moves to the lowest neighbor the river hasn't been to, so rivers fill pits and carry on.
+pub fn carve_rivers(
+    hight_map: &mut [f32],
+    biome_map: &mut [Handle<Biome>],
+    size: usize,
+    mut rng: impl Rng,
+    rivers: &Rivers,
+) {
+    let mut carved = 0;
+    for _ in 0..rivers.count * 50 {
+        if carved >= rivers.count {
+            break;
+        }
+        let source = rng.gen_range(0..hight_map.len());
+        if hight_map[source] < SOURCE_HIGHT
+            || biome_map[source] == rivers.biome
+            || biome_map[source] == rivers.sea
+        {
+            continue;
+        }
+        let mut path = vec![(source, hight_map[source])];
+        let mut visited = bevy::utils::HashSet::new();
+        visited.insert(source);
+        let mut level = hight_map[source];
+        let mut reached = false;
+        while path.len() < MAX_RIVER_LENGTH {
+            let (current, _) = *path.last().expect("path starts with the source");
+            let size = size as isize;
+            let x = current as isize % size;
+            let z = current as isize / size;
+            let next = NEIGHBORS
+                .iter()
+                .map(|(dx, dz)| (x + dx, z + dz))
+                .filter(|(x, z)| (0..size).contains(x) && (0..size).contains(z))
+                .map(|(x, z)| (x + z * size) as usize)
+                .filter(|index| !visited.contains(index))
+                .min_by(|a, b| hight_map[*a].total_cmp(&hight_map[*b]));
+            let Some(next) = next else {
+                break;
+            };
+            if biome_map[next] == rivers.sea || biome_map[next] == rivers.biome {
+                reached = true;
+                break;
+            }
+            level = level.min(hight_map[next]);
+            visited.insert(next);
+            path.push((next, level));
+        }
+        if !reached {
+            continue;
+        }
+        carved += 1;
+        for (index, level) in path {
+            hight_map[index] = (level - RIVER_DEPTH).max(0.);
+            biome_map[index] = rivers.biome.clone();
+        }
+    }
+    if carved < rivers.count {
+        warn!("Only carved {carved} of {} rivers", rivers.count);
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use thiserror::Error;
+
+use super::{loading::Progress, Biome, BiomeRule, Terrain, TerrainSettings};
+
+/// Hand made map images to build the terrain from instead of noise,
+/// set with `--heightmap <png>` and optionally `--biome-map <png>`
+#[derive(Resource, Clone, Debug)]
+pub struct ImportMap {
+    pub hight: PathBuf,
+    pub biomes: Option<PathBuf>,
+}
+
+impl ImportMap {
+    pub fn from_args() -> Option<ImportMap> {
+        let mut hight = None;
+        let mut biomes = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--heightmap" => hight = args.next().map(PathBuf::from),
+                "--biome-map" => biomes = args.next().map(PathBuf::from),
+                _ => {}
+            }
+        }
+        Some(ImportMap {
+            hight: hight?,
+            biomes,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MapImportError {
+    #[error("could not read {}: {source}", path.display())]
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("{} is {width}x{height} but the map is {size}x{size}", path.display())]
+    Size {
+        path: PathBuf,
+        width: u32,
+        height: u32,
+        size: u32,
+    },
+    #[error("{} has color #{:02X}{:02X}{:02X} at ({x}, {z}) which is not the color of any biome", path.display(), color[0], color[1], color[2])]
+    UnknownColor {
+        path: PathBuf,
+        color: [u8; 3],
+        x: u32,
+        z: u32,
+    },
+}
+
+fn open(path: &Path, size: u32) -> Result<image::DynamicImage, MapImportError> {
+    let image = image::ImageReader::open(path)
+        .map_err(image::ImageError::IoError)
+        .and_then(|reader| reader.with_guessed_format().map_err(image::ImageError::IoError))
+        .and_then(|reader| reader.decode())
+        .map_err(|source| MapImportError::Image {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    if image.width() != size || image.height() != size {
+        return Err(MapImportError::Size {
+            path: path.to_path_buf(),
+            width: image.width(),
+            height: image.height(),
+            size,
+        });
+    }
+    Ok(image)
+}
+
+/// the color of each biome used by the rules, for matching biome map pixels,
+/// when several biomes share a color the first rule using it wins
+pub(super) fn biome_colors(
+    rules: &[BiomeRule],
+    biomes: &Assets<Biome>,
+) -> Vec<([u8; 3], Handle<Biome>)> {
+    let mut colors = Vec::new();
+    for rule in rules {
+        let Some(biome) = biomes.get(&rule.biome) else {
+            continue;
+        };
+        let color = biome.color.to_srgba().to_u8_array_no_alpha();
+        if colors.iter().any(|(c, _)| *c == color) {
+            continue;
+        }
+        colors.push((color, rule.biome.clone()));
+    }
+    colors
+}
+
+impl Terrain {
+    /// Builds a terrain from a grayscale heightmap, black is the lowest point and white the highest.
+    ///
+    /// Each pixel of the biome map is matched to the biome with the same color from [`biome_colors`].
+    /// Without a biome map the rules pick biomes the same as for generated terrain,
+    /// temperature and moisture always come from the settings, the images must be `size` pixels across.
+    pub fn from_png(
+        settings: &TerrainSettings,
+        map: &ImportMap,
+        rules: &[BiomeRule],
+        colors: &[([u8; 3], Handle<Biome>)],
+        progress: &Progress,
+    ) -> Result<Terrain, MapImportError> {
+        progress.set("Reading hight map", 0.);
+        let hight_map = open(&map.hight, settings.size)?
+            .into_luma16()
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+            .collect::<Vec<_>>();
+        let (_, heat_map, moisture_map) = Terrain::noise_maps(settings);
+
+        progress.set("Picking biomes", 0.5);
+        let biome_map = if let Some(path) = &map.biomes {
+            let image = open(path, settings.size)?.into_rgb8();
+            let mut map = Vec::with_capacity(hight_map.len());
+            for (x, z, pixel) in image.enumerate_pixels() {
+                let Some((_, biome)) = colors.iter().find(|(color, _)| *color == pixel.0) else {
+                    return Err(MapImportError::UnknownColor {
+                        path: path.clone(),
+                        color: pixel.0,
+                        x,
+                        z,
+                    });
+                };
+                map.push(biome.clone());
+            }
+            map
+        } else {
+            BiomeRule::generate_map(rules, &heat_map, &hight_map, &moisture_map)
+        };
+
+        Ok(Terrain {
+            settings: settings.clone(),
+            hight_map,
+            heat_map,
+            moisture_map,
+            biome_map,
+        })
+    }
+}
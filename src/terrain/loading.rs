@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+
+pub fn plugin(app: &mut App) {
+    app.init_state::<TerrainState>()
+        .enable_state_scoped_entities::<TerrainState>()
+        .init_resource::<LoadingProgress>()
+        .add_systems(OnEnter(TerrainState::Loading), spawn_loading_screen)
+        .add_systems(
+            Update,
+            update_loading_screen.run_if(in_state(TerrainState::Loading)),
+        );
+}
+
+/// Gameplay systems only run once the terrain is `Ready`
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TerrainState {
+    /// generating the terrain or building the chunks around the player
+    #[default]
+    Loading,
+    Ready,
+}
+
+/// What the loading is doing and how far through it is (0..1),
+/// cloned into background tasks so they can report back
+#[derive(Clone)]
+pub struct Progress(Arc<Mutex<(&'static str, f32)>>);
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress(Arc::new(Mutex::new(("Loading", 0.))))
+    }
+}
+
+impl Progress {
+    pub fn set(&self, stage: &'static str, done: f32) {
+        if let Ok(mut progress) = self.0.lock() {
+            *progress = (stage, done.clamp(0., 1.));
+        }
+    }
+
+    pub fn get(&self) -> (&'static str, f32) {
+        self.0.lock().map_or(("Loading", 0.), |progress| *progress)
+    }
+}
+
+/// progress shown on the loading screen
+#[derive(Resource, Default)]
+pub struct LoadingProgress(pub Progress);
+
+#[derive(Component)]
+struct LoadingText;
+
+fn spawn_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+            // above the other ui
+            GlobalZIndex(10),
+            StateScoped(TerrainState::Loading),
+        ))
+        .with_children(|p| {
+            p.spawn((Text::default(), LoadingText));
+        });
+}
+
+fn update_loading_screen(
+    progress: Res<LoadingProgress>,
+    mut text: Query<&mut Text, With<LoadingText>>,
+) {
+    let (stage, done) = progress.0.get();
+    for mut text in &mut text {
+        text.0 = format!("{stage}... {:.0}%", done * 100.);
+    }
+}
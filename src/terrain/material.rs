@@ -0,0 +1,187 @@
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::{
+        render_resource::{
+            AsBindGroup, Extent3d, ShaderRef, TextureFormat, TextureViewDescriptor,
+            TextureViewDimension,
+        },
+        texture::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor},
+    },
+    utils::HashMap,
+};
+
+use super::{Biome, BiomeRuleSet, Biomes};
+
+const SHADER_ASSET_PATH: &str = "shaders/terrain.wgsl";
+/// size of the plain layer used by biomes without a detail texture
+const PLAIN_SIZE: u32 = 4;
+
+pub fn plugin(app: &mut App) {
+    app.add_plugins(MaterialPlugin::<TerrainMaterial>::default())
+        .init_resource::<DetailTextures>()
+        .add_systems(Update, build_detail_textures);
+}
+
+pub type TerrainMaterial = ExtendedMaterial<StandardMaterial, BiomeSplat>;
+
+/// Blends the detail textures of the biomes of neighboring tiles
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct BiomeSplat {
+    /// one texel per tile, rgb is the biome color and alpha its layer in the detail texture
+    #[texture(100)]
+    pub biomes: Handle<Image>,
+    #[texture(101, dimension = "2d_array")]
+    #[sampler(102)]
+    pub detail: Handle<Image>,
+}
+
+impl MaterialExtension for BiomeSplat {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+/// Every biome detail texture stacked into one array texture
+#[derive(Resource)]
+pub struct DetailTextures {
+    pub array: Handle<Image>,
+    /// layer of each biome in `array`, empty until the biomes have loaded
+    pub layers: HashMap<AssetId<Biome>, u8>,
+}
+
+impl FromWorld for DetailTextures {
+    fn from_world(world: &mut World) -> Self {
+        DetailTextures {
+            array: world.resource::<Assets<Image>>().reserve_handle(),
+            layers: HashMap::new(),
+        }
+    }
+}
+
+impl DetailTextures {
+    pub fn ready(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    pub fn layer(&self, biome: AssetId<Biome>) -> u8 {
+        self.layers.get(&biome).copied().unwrap_or_default()
+    }
+}
+
+/// stacks the detail textures once the biomes load and again when a biome or its texture loads or changes
+fn build_detail_textures(
+    mut biome_events: EventReader<AssetEvent<Biome>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    biome_rules: Res<Biomes>,
+    rule_sets: Res<Assets<BiomeRuleSet>>,
+    biomes: Res<Assets<Biome>>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut details: ResMut<DetailTextures>,
+) {
+    let Some(rule_set) = rule_sets.get(&biome_rules.0) else {
+        return;
+    };
+    let textures = rule_set
+        .biomes()
+        .iter()
+        .filter_map(|biome| biomes.get(biome)?.texture.as_ref())
+        .map(|texture| texture.id())
+        .collect::<Vec<_>>();
+    // a biome or texture that loads after the first build, like one added to the rules
+    // while they hot reload, needs a rebuild as much as one that changed
+    let changed = biome_events.read().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Added { .. }
+                | AssetEvent::Modified { .. }
+                | AssetEvent::LoadedWithDependencies { .. }
+        )
+    }) | image_events.read().any(|event| match event {
+        AssetEvent::Added { id }
+        | AssetEvent::Modified { id }
+        | AssetEvent::LoadedWithDependencies { id } => textures.contains(id),
+        _ => false,
+    });
+    if details.ready() && !changed {
+        return;
+    }
+    if !asset_server.is_loaded_with_dependencies(&biome_rules.0) {
+        return;
+    }
+
+    let mut size = None;
+    for texture in &textures {
+        let Some(image) = images.get(*texture) else {
+            continue;
+        };
+        let image_size = image.texture_descriptor.size;
+        match size {
+            None => size = Some(image_size),
+            Some(size) if size != image_size => {
+                error!(
+                    "Biome detail textures are different sizes ({}x{} and {}x{}), they must all match",
+                    size.width, size.height, image_size.width, image_size.height
+                );
+                return;
+            }
+            _ => {}
+        }
+    }
+    let size = size.unwrap_or(Extent3d {
+        width: PLAIN_SIZE,
+        height: PLAIN_SIZE,
+        depth_or_array_layers: 1,
+    });
+
+    let mut data = Vec::new();
+    let mut layers = HashMap::new();
+    for biome_handle in rule_set.biomes() {
+        let Some(biome) = biomes.get(biome_handle) else {
+            continue;
+        };
+        let layer_data = match biome.texture.as_ref().and_then(|texture| images.get(texture)) {
+            Some(image) if image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb => {
+                image.data.clone()
+            }
+            Some(image) => match image.convert(TextureFormat::Rgba8UnormSrgb) {
+                Some(image) => image.data,
+                None => {
+                    error!("Detail texture of biome {} has an unsupported format", biome.name);
+                    return;
+                }
+            },
+            None => vec![255; (size.width * size.height * 4) as usize],
+        };
+        layers.insert(biome_handle.id(), layers.len() as u8);
+        data.extend(layer_data);
+    }
+    if layers.is_empty() {
+        return;
+    }
+    let mut array = Image::new(
+        Extent3d {
+            width: size.width,
+            height: size.height * layers.len() as u32,
+            depth_or_array_layers: 1,
+        },
+        bevy::render::render_resource::TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        bevy::asset::RenderAssetUsages::all(),
+    );
+    array.reinterpret_stacked_2d_as_array(layers.len() as u32);
+    // a single layer would otherwise get a plain 2d view
+    array.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    array.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        ..ImageSamplerDescriptor::linear()
+    });
+    images.insert(&details.array, array);
+    details.layers = layers;
+}
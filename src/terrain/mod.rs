@@ -1,186 +1,194 @@
-use std::{
-    borrow::Cow,
-    hash::{Hash, Hasher},
-    usize,
-};
-
 use bevy::{
-    asset::RenderAssetUsages,
+    asset::RecursiveDependencyLoadState,
     ecs::system::SystemId,
     prelude::*,
-    render::{
-        render_resource::{Extent3d, TextureFormat},
-        texture::ImageSampler,
-    },
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
 
-use crate::{path_finding::MoveCost, ui::ContextActions, Cell, Path, Player, Root, Target};
+use crate::{
+    path_finding::Goal,
+    world_seed::{Stream, WorldSeed},
+    Player, Target,
+};
 
+mod biomes;
+mod chunks;
+mod cliffs;
+mod editor;
+mod erosion;
+mod import;
+mod loading;
+mod material;
 mod objects;
+mod planes;
+mod props;
+mod sample;
+mod save;
+mod scatter;
+mod settings;
+#[cfg(debug_assertions)]
+mod watchtower;
+
+use biomes::{Biome, BiomeRule, BiomeRuleSet, VOID};
+pub use chunks::Chunk;
+pub use editor::editing;
+pub use import::ImportMap;
+pub use loading::TerrainState;
+use loading::{LoadingProgress, Progress};
+pub use planes::{Plane, Transitions};
+#[cfg(test)]
+pub use planes::{Transition, TransitionKind};
+use scatter::{Scatter, ScatterSet};
+pub use settings::TerrainSettings;
 
 pub fn plugin(app: &mut App) {
-    app.init_asset::<Biome>()
+    app.add_plugins((
+        biomes::plugin,
+        cliffs::plugin,
+        editor::plugin,
+        loading::plugin,
+        material::plugin,
+        objects::plugin,
+        planes::plugin,
+        props::plugin,
+        chunks::plugin,
+        save::plugin,
+        scatter::plugin,
+        settings::plugin,
+    ))
         .init_resource::<Biomes>()
         .init_resource::<MoveTarget>()
         .init_resource::<TerrainContext>()
-        .add_plugins(objects::plugin)
-        .add_systems(Update, add_terrain_mesh);
+        .add_systems(Update, spawn_terrain);
+    #[cfg(debug_assertions)]
+    app.add_plugins(watchtower::plugin);
 }
 
-const MAP_SIZE: isize = 1000;
-const UMAP_SIZE: usize = MAP_SIZE as usize;
-const HALF_MAP: isize = MAP_SIZE / 2;
-const MAP_VOLUME: usize = (MAP_SIZE * MAP_SIZE) as usize;
-
 #[derive(Component)]
 pub struct Terrain {
-    seed: u32,
+    /// the settings the terrain was made with
+    settings: TerrainSettings,
     hight_map: Vec<f32>,
     heat_map: Vec<f32>,
+    moisture_map: Vec<f32>,
     biome_map: Vec<Handle<Biome>>,
 }
 
 impl Terrain {
-    pub fn new(seed: u32) -> Terrain {
-        let noise: noise::Fbm<noise::OpenSimplex> = noise::Fbm::new(seed);
-        use noise::NoiseFn;
-        let mut hights = Vec::with_capacity(MAP_VOLUME);
-        let mut heats = Vec::with_capacity(MAP_VOLUME);
-        for z in -HALF_MAP..HALF_MAP {
-            for x in -HALF_MAP..HALF_MAP {
-                hights.push(
-                    ((noise.get([x as f64 * 0.026, z as f64 * 0.026]) as f32 + 0.2) * 2.25)
-                        .clamp(0., 1.),
-                );
-                heats.push(
-                    ((noise.get([x as f64 * 0.0036, z as f64 * 0.0016]) as f32 + 0.2) * 2.5)
-                        .clamp(0., 1.),
-                );
-            }
+    /// slow for big maps, [`spawn_terrain`] runs it in the background
+    pub fn new(
+        settings: &TerrainSettings,
+        seed: WorldSeed,
+        rule_set: &BiomeRuleSet,
+        progress: &Progress,
+    ) -> Terrain {
+        progress.set("Shaping the land", 0.);
+        let (mut hights, heats, moistures) = Terrain::noise_maps(settings);
+        let size = settings.size as usize;
+        progress.set("Eroding", 0.2);
+        erosion::erode(&mut hights, size, seed.rng(Stream::Erosion));
+        progress.set("Picking biomes", 0.7);
+        let mut biomes = BiomeRule::generate_map(&rule_set.rules, &heats, &hights, &moistures);
+        if let Some(rivers) = &rule_set.rivers {
+            progress.set("Carving rivers", 0.8);
+            let rng = seed.rng(Stream::Rivers);
+            erosion::carve_rivers(&mut hights, &mut biomes, size, rng, rivers);
         }
-
-        let biomes = BiomeRule::generate_map::<UMAP_SIZE, UMAP_SIZE>(
-            &[
-                BiomeRule {
-                    priority: 0,
-                    biome: Biome::get_handel("Grass"),
-                    min_hight: 0.2,
-                    max_hight: 0.8,
-                    min_temperature: 0.,
-                    max_temperature: 1.,
-                },
-                BiomeRule {
-                    priority: -1,
-                    biome: Biome::get_handel("Water"),
-                    min_hight: 0.0,
-                    max_hight: 0.5,
-                    min_temperature: 0.,
-                    max_temperature: 1.,
-                },
-                BiomeRule {
-                    priority: 0,
-                    biome: Biome::get_handel("Sand"),
-                    min_hight: 0.1,
-                    max_hight: 0.2,
-                    min_temperature: 0.,
-                    max_temperature: 1.,
-                },
-                BiomeRule {
-                    priority: -1,
-                    biome: Biome::get_handel("Mountain"),
-                    min_hight: 0.5,
-                    max_hight: 1.0,
-                    min_temperature: 0.,
-                    max_temperature: 1.,
-                },
-                BiomeRule {
-                    priority: -1,
-                    biome: Biome::get_handel("Mountain_Snow"),
-                    min_hight: 0.8,
-                    max_hight: 1.0,
-                    min_temperature: 0.,
-                    max_temperature: 0.5,
-                },
-            ],
-            &heats,
-            &hights,
-        );
         Terrain {
-            seed,
+            settings: settings.clone(),
             heat_map: heats,
+            moisture_map: moistures,
             hight_map: hights,
             biome_map: biomes,
         }
     }
 
-    pub fn make_mesh(&self) -> Mesh {
-        let mut mesh = Mesh::new(
-            bevy::render::mesh::PrimitiveTopology::TriangleList,
-            RenderAssetUsages::all(),
-        );
-        let mut points = Vec::new();
-        let mut indices = Vec::new();
-        let mut uvs = Vec::new();
-        for z in 0..MAP_SIZE {
-            for x in 0..MAP_SIZE {
-                let index = (x + z * MAP_SIZE) as u32;
-                let hight = self.hight_map[index as usize];
-                points.push([(x - HALF_MAP) as f32, hight * 10., (z - HALF_MAP) as f32]);
-                uvs.push([x as f32 / MAP_SIZE as f32, z as f32 / MAP_SIZE as f32]);
-                if x == MAP_SIZE - 1 || z == MAP_SIZE - 1 {
-                    continue;
-                }
-                indices.extend_from_slice(&[
-                    index + 1,
-                    index + MAP_SIZE as u32,
-                    index + MAP_SIZE as u32 + 1,
-                    index,
-                    index + MAP_SIZE as u32,
-                    index + 1,
+    /// generates the hight, heat and moisture maps from the settings
+    fn noise_maps(settings: &TerrainSettings) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        use noise::{MultiFractal, NoiseFn};
+        let noise: noise::Fbm<noise::OpenSimplex> =
+            noise::Fbm::new(settings.seed).set_octaves(settings.octaves);
+        // own seed so rainfall doesn't follow the hills
+        let rain: noise::Fbm<noise::OpenSimplex> =
+            noise::Fbm::new(settings.seed.wrapping_add(1)).set_octaves(settings.octaves);
+        let half = settings.size as isize / 2;
+        let volume = settings.size as usize * settings.size as usize;
+        let sea_offset = settings.sea_offset();
+        let mut hights = Vec::with_capacity(volume);
+        let mut heats = Vec::with_capacity(volume);
+        let mut moistures = Vec::with_capacity(volume);
+        for z in -half..half {
+            for x in -half..half {
+                let (x, z) = (x as f64, z as f64);
+                let hight = noise.get([x * settings.hight_frequency, z * settings.hight_frequency]);
+                let heat =
+                    noise.get([x * settings.heat_frequency.x, z * settings.heat_frequency.y]);
+                let moisture = rain.get([
+                    x * settings.moisture_frequency,
+                    z * settings.moisture_frequency,
                 ]);
+                hights.push(((hight as f32 + 0.2) * 2.25 + sea_offset).clamp(0., 1.));
+                heats.push(((heat as f32 + 0.2) * 2.5).clamp(0., 1.));
+                moistures.push((moisture as f32 * 1.5 + 0.5).clamp(0., 1.));
             }
         }
-        mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        (hights, heats, moistures)
+    }
 
-        mesh
+    /// tiles along each side of the map
+    fn size(&self) -> isize {
+        self.settings.size as isize
     }
 
-    pub fn make_texture(&self, biomes: &Assets<Biome>) -> Image {
-        let size = Extent3d {
-            width: MAP_SIZE as u32,
-            height: MAP_SIZE as u32,
-            depth_or_array_layers: 1,
-        };
-        let mut data = Vec::new();
-        for z in 0..MAP_SIZE {
-            for x in 0..MAP_SIZE {
-                let index = (x + z * MAP_SIZE) as usize;
-                let biome = &self.biome_map[index];
-                let Some(biome) = biomes.get(biome.id()) else {
-                    warn!("biome not loaded");
-                    continue;
-                };
-                let color = biome.color.to_srgba();
-                data.extend_from_slice(&[
-                    (color.red * 256.) as u8,
-                    (color.green * 256.) as u8,
-                    (color.blue * 256.) as u8,
-                    255,
-                ]);
-            }
+    /// the map is centred on the origin so tiles go from -half to half
+    fn half_size(&self) -> isize {
+        self.size() / 2
+    }
+
+    /// the first tile of the map and the number of tiles along x and z
+    pub fn bounds(&self) -> (IVec2, IVec2) {
+        (
+            IVec2::splat(-self.half_size() as i32),
+            IVec2::splat(self.size() as i32),
+        )
+    }
+
+    /// index into the maps for a tile, None if the tile is outside the map
+    fn tile_index(&self, tile: IVec3) -> Option<usize> {
+        let size = self.size();
+        let x = tile.x as isize + self.half_size();
+        let z = tile.z as isize + self.half_size();
+        if (0..size).contains(&x) && (0..size).contains(&z) {
+            Some((x + z * size) as usize)
+        } else {
+            None
         }
+    }
+
+    /// the tile at an index into the maps
+    fn index_tile(&self, index: usize) -> IVec3 {
+        IVec3::new(
+            (index as isize % self.size() - self.half_size()) as i32,
+            0,
+            (index as isize / self.size() - self.half_size()) as i32,
+        )
+    }
 
-        let mut image = Image::new(
-            size,
-            bevy::render::render_resource::TextureDimension::D2,
-            data,
-            TextureFormat::Rgba8UnormSrgb,
-            RenderAssetUsages::all(),
+    /// re-picks the biome of every tile, used when the rule set changes,
+    /// river tiles stay rivers since the land was already carved for them
+    fn apply_rules(&mut self, rule_set: &BiomeRuleSet) {
+        let biome_map = BiomeRule::generate_map(
+            &rule_set.rules,
+            &self.heat_map,
+            &self.hight_map,
+            &self.moisture_map,
         );
-        image.sampler = ImageSampler::nearest();
-        image
+        let river = rule_set.rivers.as_ref().map(|rivers| &rivers.biome);
+        for (old, new) in self.biome_map.iter_mut().zip(biome_map) {
+            if Some(&*old) != river {
+                *old = new;
+            }
+        }
     }
 }
 
@@ -193,7 +201,11 @@ struct TerrainContext {
 #[derive(Resource, Default)]
 struct MoveTarget(IVec3, Option<Entity>);
 
-fn set_move_target(mut click: EventReader<Pointer<Click>>, mut target: ResMut<MoveTarget>) {
+fn set_move_target(
+    mut click: EventReader<Pointer<Click>>,
+    mut target: ResMut<MoveTarget>,
+    planes: Query<&Plane>,
+) {
     let Some(click) = click
         .read()
         .filter(|click| click.button == PointerButton::Secondary)
@@ -205,7 +217,8 @@ fn set_move_target(mut click: EventReader<Pointer<Click>>, mut target: ResMut<Mo
     target.1 = Some(click.target);
     target.0 = if let Some(mut pos) = click.hit.position {
         info!("right click target is {}", pos.round());
-        pos.y = 0.;
+        // floors have a plane, everything else is on the ground
+        pos.y = planes.get(click.target).map_or(0, |plane| plane.0) as f32;
         pos.round().as_ivec3()
     } else {
         error!("No Hit Data");
@@ -219,7 +232,7 @@ fn on_walk_context(
     target: Res<MoveTarget>,
 ) {
     for path in &player {
-        commands.entity(path).insert(Target(target.0));
+        commands.entity(path).insert(Target(Goal::Tile(target.0)));
     }
 }
 
@@ -234,190 +247,179 @@ impl FromWorld for TerrainContext {
     }
 }
 
+/// the rule set every new [`Terrain`] is generated from
 #[derive(Resource)]
-pub struct Biomes(Vec<Handle<Biome>>);
-
-#[derive(Component)]
-struct BiomeCell(pub Handle<Biome>);
+pub struct Biomes(Handle<BiomeRuleSet>);
 
 impl FromWorld for Biomes {
     fn from_world(world: &mut World) -> Self {
-        let mut assets = world.resource_mut::<Assets<Biome>>();
-        let mut out = Vec::new();
-        assets.insert(
-            Biome::get_handel("Grass").id(),
-            Biome {
-                name: "Grass".into(),
-                move_cost: 10.,
-                color: bevy::color::palettes::css::GREEN.into(),
-            },
-        );
-        out.push(
-            assets
-                .get_strong_handle(Biome::get_handel("Grass").id())
-                .unwrap(),
-        );
-        assets.insert(
-            Biome::get_handel("Water").id(),
-            Biome {
-                name: "Water".into(),
-                move_cost: f32::INFINITY,
-                color: bevy::color::palettes::css::NAVY.into(),
-            },
-        );
-        out.push(
-            assets
-                .get_strong_handle(Biome::get_handel("Water").id())
-                .unwrap(),
-        );
-        assets.insert(
-            Biome::get_handel("Sand").id(),
-            Biome {
-                name: "Sand".into(),
-                move_cost: 15.,
-                color: bevy::color::palettes::css::YELLOW.into(),
-            },
-        );
-        out.push(
-            assets
-                .get_strong_handle(Biome::get_handel("Sand").id())
-                .unwrap(),
-        );
-        assets.insert(
-            Biome::get_handel("Mountain").id(),
-            Biome {
-                name: "Mountain".into(),
-                move_cost: f32::INFINITY,
-                color: bevy::color::palettes::css::GRAY.into(),
-            },
-        );
-        out.push(
-            assets
-                .get_strong_handle(Biome::get_handel("Mountain").id())
-                .unwrap(),
-        );
-        assets.insert(
-            Biome::get_handel("Mountain_Snow").id(),
-            Biome {
-                name: "Mountain_Snow".into(),
-                move_cost: f32::INFINITY,
-                color: bevy::color::palettes::css::GRAY.into(),
-            },
-        );
-        out.push(
-            assets
-                .get_strong_handle(Biome::get_handel("Mountain_Snow").id())
-                .unwrap(),
-        );
-        Biomes(out)
+        Biomes(world.resource::<AssetServer>().load("biomes/default.rules.ron"))
     }
 }
 
-fn add_terrain_mesh(
+#[derive(Component)]
+struct BiomeCell(pub Handle<Biome>);
+
+/// terrain being generated in the background and the settings it was started with
+type Generating = (
+    TerrainSettings,
+    Task<Result<(Terrain, objects::Objects), import::MapImportError>>,
+);
+
+/// generates the terrain in the background once the biome and scatter rules have loaded,
+/// spawns it when it is done
+fn spawn_terrain(
     mut commands: Commands,
+    terrains: Query<(), With<Terrain>>,
+    biomes: Res<Biomes>,
+    rule_sets: Res<Assets<BiomeRuleSet>>,
+    biome_assets: Res<Assets<Biome>>,
+    scatter: Res<Scatter>,
+    scatter_sets: Res<Assets<ScatterSet>>,
     asset_server: Res<AssetServer>,
-    terrains: Query<(Entity, &Terrain), (Added<Terrain>, Without<Mesh3d>)>,
-    biomes: Res<Assets<Biome>>,
-    context: Res<TerrainContext>,
+    import: Option<Res<ImportMap>>,
+    settings: Res<TerrainSettings>,
+    progress: Res<LoadingProgress>,
+    mut state: ResMut<NextState<TerrainState>>,
+    mut failed: Local<Option<TerrainSettings>>,
+    mut generating: Local<Option<Generating>>,
 ) {
-    for (entity, terrain) in &terrains {
-        let texture = asset_server.add(terrain.make_texture(&biomes));
-        commands
-            .entity(entity)
-            .insert((
-                Name::new("Terrain"),
-                Root,
-                Mesh3d(asset_server.add(terrain.make_mesh())),
-                MeshMaterial3d(asset_server.add(StandardMaterial {
-                    base_color: Color::WHITE,
-                    base_color_texture: Some(texture),
-                    unlit: true,
-                    ..Default::default()
-                })),
-                ContextActions {
-                    on_open: Some(context.on_open),
-                    options: vec![("Walk".into(), context.walk)],
-                    on_close: None,
-                },
-            ))
-            .with_children(|commands| {
-                for z in -HALF_MAP..HALF_MAP {
-                    for x in -HALF_MAP..HALF_MAP {
-                        let index = (x + HALF_MAP + (z + HALF_MAP) * MAP_SIZE) as usize;
-                        let hight = terrain.hight_map[index];
-                        let biome_handle = &terrain.biome_map[index];
-                        let Some(biome) = biomes.get(biome_handle) else {
-                            warn!("biome not loaded");
-                            continue;
-                        };
-                        let color = biome.color;
-                        let cost = biome.move_cost;
-
-                        commands.spawn((
-                            BiomeCell(biome_handle.clone()),
-                            Transform::from_translation(Vec3::new(x as f32, hight * 10., z as f32)),
-                            Cell,
-                            MoveCost(cost),
-                        ));
-                    }
+    if let Some((started, task)) = &mut *generating {
+        if !terrains.is_empty() || *started != *settings {
+            // a world was loaded or the settings changed. Generation doesn't stop part way
+            // so a task that started runs to the end in the background, its terrain is thrown away
+            *generating = None;
+        } else {
+            let Some(result) = block_on(future::poll_once(task)) else {
+                return;
+            };
+            *generating = None;
+            match result {
+                Ok((terrain, objects)) => {
+                    commands.spawn((
+                        Transform::default(),
+                        Visibility::default(),
+                        Name::new("Terrain"),
+                        objects,
+                        terrain,
+                    ));
                 }
-            });
+                Err(e) => {
+                    error!("Can't import terrain: {e}");
+                    *failed = Some(settings.clone());
+                }
+            }
+            return;
+        }
     }
-}
-
-struct BiomeRule {
-    priority: i8,
-    biome: Handle<Biome>,
-    min_hight: f32,
-    max_hight: f32,
-    min_temperature: f32,
-    max_temperature: f32,
-}
-
-#[derive(Clone, Reflect, Asset)]
-struct Biome {
-    name: Cow<'static, str>,
-    move_cost: f32,
-    color: Color,
-}
-
-impl Biome {
-    fn get_handel(name: impl AsRef<str>) -> Handle<Biome> {
-        let mut hasher = std::hash::DefaultHasher::new();
-        name.as_ref().hash(&mut hasher);
-        Handle::Weak(AssetId::Uuid {
-            uuid: uuid::Uuid::from_u128(hasher.finish() as u128),
-        })
+    // after a failure only try again once the settings change
+    if !terrains.is_empty() || failed.as_ref() == Some(&*settings) {
+        return;
+    }
+    for (what, load_state) in [
+        (
+            "biomes",
+            asset_server.get_recursive_dependency_load_state(&biomes.0),
+        ),
+        (
+            "scatter rules",
+            asset_server.get_recursive_dependency_load_state(&scatter.0),
+        ),
+    ] {
+        match load_state {
+            Some(RecursiveDependencyLoadState::Loaded) => {}
+            Some(RecursiveDependencyLoadState::Failed(e)) => {
+                error!("Can't generate terrain, {what} failed to load: {e}");
+                *failed = Some(settings.clone());
+                return;
+            }
+            _ => return,
+        }
+    }
+    if let Err(e) = settings.validate() {
+        error!("Can't generate terrain: {e}");
+        *failed = Some(settings.clone());
+        return;
     }
+    let (Some(rule_set), Some(scatter)) = (rule_sets.get(&biomes.0), scatter_sets.get(&scatter.0))
+    else {
+        return;
+    };
+    // everything the task needs from the world is copied into it
+    let rule_set = rule_set.clone();
+    let colors = import::biome_colors(&rule_set.rules, &biome_assets);
+    let scatter = scatter.clone();
+    let import = import.map(|import| import.clone());
+    let task_settings = settings.clone();
+    let seed = WorldSeed::from(&*settings);
+    let progress = progress.0.clone();
+    progress.set("Loading", 0.);
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let settings = task_settings;
+        let terrain = match import {
+            Some(import) => {
+                Terrain::from_png(&settings, &import, &rule_set.rules, &colors, &progress)?
+            }
+            None => Terrain::new(&settings, seed, &rule_set, &progress),
+        };
+        progress.set("Placing objects", 0.9);
+        let objects = objects::Objects::generate(&terrain, seed, &scatter);
+        Ok((terrain, objects))
+    });
+    *generating = Some((settings.clone(), task));
+    state.set(TerrainState::Loading);
 }
 
 impl BiomeRule {
-    fn generate_map<const W: usize, const H: usize>(
+    fn generate_map(
         biomes: &[BiomeRule],
         heat_map: &[f32],
         hight_map: &[f32],
+        moisture_map: &[f32],
     ) -> Vec<Handle<Biome>> {
-        let mut map = Vec::with_capacity(W * H);
-        for h in 0..H {
-            for w in 0..W {
-                let index = w + h * W;
-                let heat = heat_map[index];
-                let hight = hight_map[index];
-                let mut options = biomes.iter().collect::<Vec<_>>();
-                options.retain(|&option| {
-                    option.max_hight >= hight
-                        && option.min_hight <= hight
-                        && option.min_temperature <= heat
-                        && option.max_temperature >= heat
-                });
-                options.sort_by(|a, b| b.priority.cmp(&a.priority));
-                if let Some(choice) = options.first() {
-                    map.push(choice.biome.clone());
-                } else {
-                    map.push(Biome::get_handel("Void"));
-                }
+        let mut map = Vec::with_capacity(hight_map.len());
+        // the tiles no rule matched and the range of their values, to help fix the rules
+        let mut unmatched = 0;
+        let mut lowest = Vec3::INFINITY;
+        let mut highest = Vec3::NEG_INFINITY;
+        for index in 0..hight_map.len() {
+            let heat = heat_map[index];
+            let hight = hight_map[index];
+            let moisture = moisture_map[index];
+            let mut options = biomes.iter().collect::<Vec<_>>();
+            options.retain(|&option| {
+                option.max_hight >= hight
+                    && option.min_hight <= hight
+                    && option.min_temperature <= heat
+                    && option.max_temperature >= heat
+                    && option.min_moisture <= moisture
+                    && option.max_moisture >= moisture
+            });
+            options.sort_by(|a, b| b.priority.cmp(&a.priority));
+            if let Some(choice) = options.first() {
+                map.push(choice.biome.clone());
+            } else {
+                let values = Vec3::new(hight, heat, moisture);
+                unmatched += 1;
+                lowest = lowest.min(values);
+                highest = highest.max(values);
+                map.push(VOID);
             }
         }
+        if unmatched > 0 {
+            warn!(
+                "{unmatched} of {} tiles ({:.2}%) matched no biome rule and are Void, \
+                they had hight {:.2}..{:.2}, temperature {:.2}..{:.2} and moisture {:.2}..{:.2}",
+                map.len(),
+                unmatched as f32 / map.len() as f32 * 100.,
+                lowest.x,
+                highest.x,
+                lowest.y,
+                highest.y,
+                lowest.z,
+                highest.z,
+            );
+        }
         map
     }
 }
@@ -1,105 +1,306 @@
-use bevy::{ecs::system::SystemId, prelude::*};
-use noise::Add;
-use rand::{Rng, SeedableRng};
+use std::sync::Arc;
 
-use crate::{ui::ContextActions, Path, Player, Target};
+use bevy::{
+    ecs::system::SystemId,
+    gltf::{GltfMesh, GltfNode},
+    prelude::*,
+    utils::HashMap,
+};
 
-use super::{Biome, BiomeCell, MoveTarget};
+use crate::{
+    path_finding::{Footprint, Goal, Passability, TileFactors, Waypoints},
+    ui::ContextActions,
+    PastCell, Path, Player, Target,
+};
 
+use super::{
+    chunks::RedrawChunk,
+    props::Prop,
+    scatter::{Scatter, ScatterSet},
+    Chunk, MoveTarget, Terrain,
+};
+
+/// the tile a scattered object is on
 #[derive(Component)]
 #[require(Age)]
-struct Tree;
+struct Scattered(IVec3);
 
 #[derive(Component, Debug, Default)]
 struct Age(f32);
 
+/// full size and how long it takes to reach it
+#[derive(Component)]
+struct Grows {
+    scale: f32,
+    time: f32,
+}
+
+/// An object placed by a [`ScatterRule`](super::scatter::ScatterRule)
+#[derive(Clone, Debug)]
+pub(super) struct Object {
+    /// name of the rule that placed it
+    pub rule: Arc<str>,
+    pub scale: f32,
+    /// around the up axis in radians
+    pub rotation: f32,
+    pub age: f32,
+}
+
+/// Every scattered object on the terrain and its state,
+/// lives on the terrain entity so objects keep their state when chunks unload
+#[derive(Component, Default)]
+pub(super) struct Objects(pub HashMap<IVec3, Object>);
+
+impl Objects {
+    /// how every object changes the cost of the tiles it stands on
+    pub(super) fn factors(&self, scatter: &ScatterSet) -> TileFactors {
+        let mut factors = TileFactors::default();
+        for (tile, object) in &self.0 {
+            if let Some(rule) = scatter.rule(&object.rule) {
+                factors.add(rule.footprint_tiles(*tile), rule.passability);
+            }
+        }
+        factors
+    }
+
+    /// how the objects standing on some tiles change their cost,
+    /// looks for objects whose footprint reaches the tiles instead of going through all of them
+    pub(super) fn factors_on(
+        &self,
+        scatter: &ScatterSet,
+        tiles: impl IntoIterator<Item = IVec3>,
+    ) -> TileFactors {
+        let mut factors = TileFactors::default();
+        for tile in tiles {
+            for rule in &scatter.rules {
+                for offset in &rule.footprint {
+                    let placed = tile - IVec3::new(offset.x, 0, offset.y);
+                    if self
+                        .0
+                        .get(&placed)
+                        .is_some_and(|object| object.rule == rule.name)
+                    {
+                        factors.add([tile], rule.passability);
+                    }
+                }
+            }
+        }
+        factors
+    }
+}
+
 #[derive(Resource)]
-struct TreeContext {
+struct ObjectContext {
     open: SystemId,
     chop: SystemId,
     walk: SystemId,
 }
 
-impl FromWorld for TreeContext {
+impl FromWorld for ObjectContext {
     fn from_world(world: &mut World) -> Self {
         let open = world.register_system(super::set_move_target);
         let walk = world.register_system(super::on_walk_context);
         let chop = world.register_system(on_chop_context);
-        TreeContext { open, chop, walk }
+        ObjectContext { open, chop, walk }
     }
 }
 
 #[derive(Component)]
 struct Chop(Entity);
 
-fn on_chop(player: Query<(Entity, &Chop, &Path), With<Player>>, mut commands: Commands) {
-    for (entity, target, path) in &player {
-        if path.0.is_empty() {
-            commands.entity(target.0).despawn_recursive();
-            commands.entity(entity).remove::<Chop>();
+fn on_chop(
+    // the path isn't done while it is still being found or has waypoints left
+    player: Query<
+        (Entity, &Chop, &Path, &PastCell),
+        (With<Player>, Without<Target>, Without<Waypoints>),
+    >,
+    scattered: Query<(&Scattered, Option<&Footprint>)>,
+    mut terrain: Query<&mut Objects>,
+    mut commands: Commands,
+) {
+    for (entity, target, path, past) in &player {
+        if !path.0.is_empty() {
+            continue;
         }
+        commands.entity(entity).remove::<Chop>();
+        let Ok((scattered, footprint)) = scattered.get(target.0) else {
+            continue;
+        };
+        // the path failed or only got as close as it could, the player was told when it was found
+        if !chop_goal(scattered, footprint).reached(past.cell) {
+            continue;
+        }
+        if let Ok(mut objects) = terrain.get_single_mut() {
+            objects.0.remove(&scattered.0);
+        }
+        commands.entity(target.0).despawn_recursive();
     }
 }
 
+/// objects are chopped standing on or next to them
+fn chop_goal(scattered: &Scattered, footprint: Option<&Footprint>) -> Goal {
+    Goal::Adjacent(footprint.map_or(vec![scattered.0], |footprint| footprint.tiles.clone()))
+}
+
 fn on_chop_context(
     mut commands: Commands,
     player: Query<Entity, With<Player>>,
     target: Res<MoveTarget>,
+    objects: Query<(&Scattered, Option<&Footprint>)>,
+    parents: Query<&Parent>,
 ) {
+    let Some(clicked) = target.1 else {
+        return;
+    };
+    // the click hits a mesh somewhere under the object
+    let Some(object) = std::iter::once(clicked)
+        .chain(parents.iter_ancestors(clicked))
+        .find(|entity| objects.contains(*entity))
+    else {
+        warn!("{clicked} is not part of an object");
+        return;
+    };
+    let Ok((scattered, footprint)) = objects.get(object) else {
+        return;
+    };
     for path in &player {
         commands
             .entity(path)
-            .insert((Target(target.0), Chop(target.1.unwrap())));
+            .insert((Target(chop_goal(scattered, footprint)), Chop(object)));
     }
 }
 
 pub fn plugin(app: &mut App) {
-    app.add_systems(Update, (rng_trees, grow_tree, update_age, on_chop))
-        .init_resource::<TreeContext>();
+    app.add_systems(
+        Update,
+        (
+            spawn_objects,
+            grow,
+            (update_age, store_age).chain(),
+            on_chop,
+            settle_objects,
+        ),
+    )
+        .init_resource::<ObjectContext>();
 }
 
-fn rng_trees(
+fn spawn_objects(
     mut commands: Commands,
-    cells: Query<(Entity, &BiomeCell, &Transform), Added<BiomeCell>>,
-    asset_server: Res<AssetServer>,
-    context: Res<TreeContext>,
+    chunks: Query<(Entity, &Chunk), Added<Chunk>>,
+    terrain: Query<(&Terrain, &Objects)>,
+    scatter: Res<Scatter>,
+    scatter_sets: Res<Assets<ScatterSet>>,
+    context: Res<ObjectContext>,
+    nodes: Res<Assets<GltfNode>>,
+    meshes: Res<Assets<GltfMesh>>,
 ) {
-    for (entity, cell, pos) in &cells {
-        let id = pos.translation.as_ivec3();
-        let seed = id.x ^ id.y ^ id.z;
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed as u64);
-        if cell.0 == Biome::get_handel("Sand") && rng.gen_bool(0.01) {
+    let Ok((terrain, objects)) = terrain.get_single() else {
+        return;
+    };
+    let Some(scatter) = scatter_sets.get(&scatter.0) else {
+        return;
+    };
+    for (entity, chunk) in &chunks {
+        for id in chunk.tiles(terrain.size()) {
+            let Some(object) = objects.0.get(&id) else {
+                continue;
+            };
+            let Some(pos) = terrain.tile_position(id) else {
+                continue;
+            };
+            let Some(rule) = scatter.rule(&object.rule) else {
+                warn!("no scatter rule for {}", object.rule);
+                continue;
+            };
+            let scale = if rule.grow_time > 0. {
+                object.scale * (object.age / rule.grow_time).min(1.)
+            } else {
+                object.scale
+            };
+            let mut options = vec![("Walk".to_string(), context.walk)];
+            if rule.choppable {
+                options.push(("Chop".to_string(), context.chop));
+            }
             commands.entity(entity).with_children(|p| {
-                p.spawn((
-                    SceneRoot(asset_server.load("tree.glb#Scene0")),
-                    Transform::from_scale(Vec3::splat(0.01)),
-                    Tree,
+                let mut object_entity = p.spawn((
+                    Transform::from_translation(pos)
+                        .with_rotation(Quat::from_rotation_y(object.rotation))
+                        .with_scale(Vec3::splat(scale)),
+                    Scattered(id),
+                    Age(object.age),
                     ContextActions {
                         on_open: Some(context.open),
                         on_close: None,
-                        options: vec![
-                            ("Walk".to_string(), context.walk),
-                            ("Chop".to_string(), context.chop),
-                        ],
+                        options,
                     },
                     Visibility::Visible,
-                    Name::new("Palm Tree"),
+                    Name::new(rule.name.to_string()),
                 ));
+                Prop::spawn(&mut object_entity, rule, &nodes, &meshes);
+                if rule.passability != Passability::Walkable {
+                    object_entity.insert(Footprint {
+                        tiles: rule.footprint_tiles(id).collect(),
+                        passability: rule.passability,
+                    });
+                }
+                if rule.grow_time > 0. {
+                    object_entity.insert(Grows {
+                        scale: object.scale,
+                        time: rule.grow_time,
+                    });
+                }
             });
         }
     }
 }
 
+/// moves objects in redrawn chunks back onto the ground
+fn settle_objects(
+    mut events: EventReader<RedrawChunk>,
+    terrain: Query<&Terrain>,
+    mut objects: Query<(&Scattered, &mut Transform)>,
+) {
+    let redraw = events.read().map(|event| event.0).collect::<Vec<_>>();
+    let Ok(terrain) = terrain.get_single() else {
+        return;
+    };
+    if redraw.is_empty() {
+        return;
+    }
+    for (scattered, mut pos) in &mut objects {
+        if !redraw.contains(&Chunk::from_world(scattered.0.as_vec3(), terrain.size())) {
+            continue;
+        }
+        if let Some(hight) = terrain.tile_hight(scattered.0) {
+            pos.translation.y = hight;
+        }
+    }
+}
+
 fn update_age(time: Res<Time>, mut objects: Query<&mut Age>) {
     for mut object in &mut objects {
         object.0 += time.delta_secs();
     }
 }
 
-fn grow_tree(mut trees: Query<(&mut Transform, &Age), With<Tree>>) {
-    for (mut pos, age) in &mut trees {
-        if age.0 < 100. {
-            pos.scale = Vec3::splat(age.0 * 0.01);
+/// copies the age of loaded objects back to [`Objects`]
+fn store_age(objects: Query<(&Scattered, &Age)>, mut terrain: Query<&mut Objects>) {
+    let Ok(mut stored) = terrain.get_single_mut() else {
+        return;
+    };
+    for (scattered, age) in &objects {
+        if let Some(stored) = stored.0.get_mut(&scattered.0) {
+            stored.age = age.0;
+        }
+    }
+}
+
+fn grow(mut objects: Query<(&mut Transform, &Age, &Grows)>) {
+    for (mut pos, age, grows) in &mut objects {
+        if age.0 < grows.time {
+            pos.scale = Vec3::splat(grows.scale * age.0 / grows.time);
+        } else if pos.scale.x != grows.scale {
+            pos.scale = Vec3::splat(grows.scale);
         }
     }
 }
@@ -0,0 +1,94 @@
+use bevy::{prelude::*, utils::HashMap};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Transitions>()
+        .add_systems(Update, update_transitions);
+}
+
+/// Which walkable layer a cell or floor is on, cells without one are on the ground (plane 0).
+/// Planes above the ground are bridges and upper floors, planes below it are underground.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Plane(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionKind {
+    Ladder,
+}
+
+impl TransitionKind {
+    /// cost of using the transition compared to a straight step
+    pub fn cost(self) -> f32 {
+        match self {
+            TransitionKind::Ladder => 2.,
+        }
+    }
+}
+
+/// The only way to move between planes, links two cells and works in both directions
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Transition {
+    pub a: IVec3,
+    pub b: IVec3,
+    pub kind: TransitionKind,
+}
+
+/// Every transition by the cells at its ends
+#[derive(Resource, Clone, Default)]
+pub struct Transitions {
+    from_cell: HashMap<IVec3, Vec<(IVec3, TransitionKind)>>,
+    by_entity: HashMap<Entity, Transition>,
+}
+
+impl Transitions {
+    /// the cells reachable from a cell through transitions
+    pub fn from(&self, cell: IVec3) -> &[(IVec3, TransitionKind)] {
+        self.from_cell
+            .get(&cell)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Transition> {
+        self.by_entity.values()
+    }
+
+    /// makes both ends of a transition reachable from the other
+    pub fn link(&mut self, transition: Transition) {
+        for (from, to) in [(transition.a, transition.b), (transition.b, transition.a)] {
+            self.from_cell
+                .entry(from)
+                .or_default()
+                .push((to, transition.kind));
+        }
+    }
+
+    fn unlink(&mut self, transition: Transition) {
+        for (from, to) in [(transition.a, transition.b), (transition.b, transition.a)] {
+            let Some(links) = self.from_cell.get_mut(&from) else {
+                continue;
+            };
+            if let Some(index) = links.iter().position(|link| *link == (to, transition.kind)) {
+                links.swap_remove(index);
+            }
+            if links.is_empty() {
+                self.from_cell.remove(&from);
+            }
+        }
+    }
+}
+
+fn update_transitions(
+    added: Query<(Entity, &Transition), Added<Transition>>,
+    mut removed: RemovedComponents<Transition>,
+    mut transitions: ResMut<Transitions>,
+) {
+    for entity in removed.read() {
+        if let Some(transition) = transitions.by_entity.remove(&entity) {
+            transitions.unlink(transition);
+        }
+    }
+    for (entity, transition) in &added {
+        transitions.link(*transition);
+        transitions.by_entity.insert(entity, *transition);
+    }
+}
@@ -0,0 +1,145 @@
+use bevy::{
+    gltf::{GltfMesh, GltfNode},
+    prelude::*,
+};
+
+use super::scatter::ScatterRule;
+
+/// objects have to move this far past `scene_distance` before the scene is swapped back out,
+/// so standing on the edge doesn't respawn it every frame
+const SWAP_MARGIN: f32 = 2.;
+
+pub fn plugin(app: &mut App) {
+    // after the objects chopped in Update are gone
+    app.add_systems(
+        PostUpdate,
+        update_props.before(TransformSystem::TransformPropagate),
+    );
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Lod {
+    Culled,
+    /// the mesh children, every object of a rule shares their mesh and material
+    /// so they are drawn in one instanced batch
+    Instanced,
+    Scene,
+}
+
+/// How a scattered object is drawn. Full scenes cost a lot to spawn and draw
+/// so far away objects are drawn with the instance mesh, and past the view distance not at all.
+#[derive(Component)]
+pub struct Prop {
+    scene: Handle<Scene>,
+    /// the scene is spawned closer than this
+    scene_distance: f32,
+    view_distance: f32,
+    /// mesh children drawn instead of the scene, empty if the rule has no instance
+    instances: Vec<Entity>,
+    lod: Option<Lod>,
+    spawned_scene: Option<Entity>,
+}
+
+impl Prop {
+    /// spawns the instance meshes under an object, it stays hidden until [`update_props`] runs
+    pub(super) fn spawn(
+        object: &mut EntityCommands,
+        rule: &ScatterRule,
+        nodes: &Assets<GltfNode>,
+        meshes: &Assets<GltfMesh>,
+    ) {
+        let mut instances = Vec::new();
+        if let Some(node) = &rule.instance {
+            let mesh = nodes
+                .get(node)
+                .and_then(|node| Some((node.transform, meshes.get(node.mesh.as_ref()?)?)));
+            if let Some((transform, mesh)) = mesh {
+                object.with_children(|p| {
+                    for primitive in &mesh.primitives {
+                        instances.push(
+                            p.spawn((
+                                Mesh3d(primitive.mesh.clone()),
+                                MeshMaterial3d(primitive.material.clone().unwrap_or_default()),
+                                transform,
+                                Visibility::Hidden,
+                            ))
+                            .id(),
+                        );
+                    }
+                });
+            } else {
+                warn!(
+                    "instance of {} has no mesh, drawing the full scene",
+                    rule.name
+                );
+            }
+        }
+        object.insert(Prop {
+            scene: rule.prefab.clone(),
+            scene_distance: rule.scene_distance,
+            view_distance: rule.view_distance,
+            instances,
+            lod: None,
+            spawned_scene: None,
+        });
+    }
+}
+
+/// picks how each object is drawn from how far it is from the camera
+fn update_props(
+    mut commands: Commands,
+    camera: Query<&GlobalTransform, With<Camera3d>>,
+    mut props: Query<(Entity, &GlobalTransform, &mut Prop)>,
+    mut visibility: Query<&mut Visibility>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let camera = camera.translation();
+    for (entity, pos, mut prop) in &mut props {
+        let distance = pos.translation().distance(camera);
+        let scene_distance = if prop.instances.is_empty() {
+            prop.view_distance
+        } else if prop.lod == Some(Lod::Scene) {
+            prop.scene_distance + SWAP_MARGIN
+        } else {
+            prop.scene_distance
+        };
+        let lod = if distance < scene_distance {
+            Lod::Scene
+        } else if distance < prop.view_distance {
+            Lod::Instanced
+        } else {
+            Lod::Culled
+        };
+        if prop.lod == Some(lod) {
+            continue;
+        }
+        prop.lod = Some(lod);
+
+        let shown = if lod == Lod::Instanced {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        for instance in &prop.instances {
+            if let Ok(mut visibility) = visibility.get_mut(*instance) {
+                *visibility = shown;
+            }
+        }
+        match (lod, prop.spawned_scene) {
+            (Lod::Scene, None) => {
+                let mut scene = None;
+                commands.entity(entity).with_children(|p| {
+                    scene = Some(p.spawn(SceneRoot(prop.scene.clone())).id());
+                });
+                prop.spawned_scene = scene;
+            }
+            (Lod::Instanced | Lod::Culled, Some(scene)) => {
+                commands.entity(scene).despawn_recursive();
+                prop.spawned_scene = None;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+
+use super::{Biome, Terrain};
+
+impl Terrain {
+    /// ground hight of a tile in world units
+    pub fn tile_hight(&self, tile: IVec3) -> Option<f32> {
+        let index = self.tile_index(tile)?;
+        Some(self.hight_map[index] * self.settings.hight_scale)
+    }
+
+    /// where a tile's centre is on the ground
+    pub fn tile_position(&self, tile: IVec3) -> Option<Vec3> {
+        let hight = self.tile_hight(tile)?;
+        Some(Vec3::new(tile.x as f32, hight, tile.z as f32))
+    }
+
+    pub fn tile_biome(&self, tile: IVec3) -> Option<&Handle<Biome>> {
+        let index = self.tile_index(tile)?;
+        Some(&self.biome_map[index])
+    }
+
+    /// steepness of a tile in radians, 0 is flat,
+    /// from the tiles either side of it the same as the mesh normals
+    pub fn tile_slope(&self, tile: IVec3) -> Option<f32> {
+        let centre = self.tile_hight(tile)?;
+        // neighbours past the edge of the map use the edge tile
+        let hight = |x: i32, z: i32| {
+            self.tile_hight(tile + IVec3::new(x, 0, z))
+                .unwrap_or(centre)
+        };
+        let gradient = Vec2::new(hight(1, 0) - hight(-1, 0), hight(0, 1) - hight(0, -1)) / 2.;
+        Some(gradient.length().atan())
+    }
+
+    /// ground hight at a world position, blended between the four tiles around it
+    pub fn hight_at(&self, pos: Vec2) -> Option<f32> {
+        let ([nw, ne, sw, se], t) = self.corners(pos)?;
+        Some(nw.lerp(ne, t.x).lerp(sw.lerp(se, t.x), t.y))
+    }
+
+    /// steepness at a world position in radians, 0 is flat
+    pub fn slope_at(&self, pos: Vec2) -> Option<f32> {
+        let ([nw, ne, sw, se], t) = self.corners(pos)?;
+        let gradient = Vec2::new((ne - nw).lerp(se - sw, t.y), (sw - nw).lerp(se - ne, t.x));
+        Some(gradient.length().atan())
+    }
+
+    /// biome of the tile nearest to a world position
+    pub fn biome_at(&self, pos: Vec2) -> Option<&Handle<Biome>> {
+        let tile = pos.round().as_ivec2();
+        self.tile_biome(IVec3::new(tile.x, 0, tile.y))
+    }
+
+    /// hights of the four tiles around a world position and how far it is between them,
+    /// None outside the map
+    fn corners(&self, pos: Vec2) -> Option<([f32; 4], Vec2)> {
+        let map = pos + self.half_size() as f32;
+        let max = (self.size() - 1) as f32;
+        if !(0. ..=max).contains(&map.x) || !(0. ..=max).contains(&map.y) {
+            return None;
+        }
+        // the last row and column use the square before them
+        let corner = map.floor().min(Vec2::splat(max - 1.));
+        let t = map - corner;
+        let tile = corner.as_ivec2() - IVec2::splat(self.half_size() as i32);
+        let hight = |x: i32, z: i32| {
+            self.tile_hight(IVec3::new(tile.x + x, 0, tile.y + z))
+                .expect("corner is inside the map")
+        };
+        Some(([hight(0, 0), hight(1, 0), hight(0, 1), hight(1, 1)], t))
+    }
+}
@@ -0,0 +1,648 @@
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use bevy::{input::common_conditions::input_just_pressed, math::DVec2, prelude::*, utils::HashMap};
+use thiserror::Error;
+
+use crate::{
+    character,
+    world_seed::{EntityRng, Stream, WorldSeed},
+    File, PastCell, Player, CHARACTERS,
+};
+
+use super::{
+    biomes::VOID,
+    objects::{Object, Objects},
+    settings::TerrainSettingsError,
+    Biome, BiomeRuleSet, Biomes, Terrain, TerrainSettings, TerrainState,
+};
+
+const MAGIC: &[u8; 8] = b"RSCWORLD";
+/// bump this when the layout of a world file changes
+///
+/// 1: first version
+/// 2: added the moisture map
+/// 3: store all the terrain settings instead of just the seed and size
+/// 4: trees became objects from scatter rules with their rule name, scale and rotation
+const VERSION: u32 = 4;
+const DEFAULT_PATH: &str = "world.save";
+/// longest name or path a world file can have, anything longer is a broken file
+const MAX_STRING: u32 = 1024;
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<SaveWorld>()
+        .add_event::<LoadWorld>()
+        .add_systems(
+            Update,
+            (
+                (|mut save: EventWriter<SaveWorld>| {
+                    save.send(SaveWorld(DEFAULT_PATH.into()));
+                })
+                .run_if(input_just_pressed(KeyCode::F5)),
+                (|mut load: EventWriter<LoadWorld>| {
+                    load.send(LoadWorld(DEFAULT_PATH.into()));
+                })
+                .run_if(input_just_pressed(KeyCode::F9)),
+                save_world,
+                load_world,
+            )
+                .chain(),
+        );
+}
+
+/// Writes the current world to a file
+#[derive(Event)]
+pub struct SaveWorld(pub PathBuf);
+
+/// Replaces the current world with one from a file
+#[derive(Event)]
+pub struct LoadWorld(pub PathBuf);
+
+#[derive(Debug, Error)]
+pub enum WorldFileError {
+    #[error("could not access world file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a world file")]
+    BadMagic,
+    #[error(
+        "world file is format version {found} but this build only reads version {VERSION}, it was saved by {} build",
+        if *found < VERSION { "an older" } else { "a newer" }
+    )]
+    Version { found: u32 },
+    #[error("world file has invalid terrain settings: {0}")]
+    Settings(#[from] TerrainSettingsError),
+    #[error("world file uses biome `{0}` which is not loaded")]
+    UnknownBiome(String),
+    #[error("world file has biome index {0} which is not in its biome table")]
+    BiomeIndex(u16),
+    #[error("world file has {count} {what}, at most {max} fit")]
+    TooMany {
+        what: &'static str,
+        count: u32,
+        max: u32,
+    },
+    #[error("world file has a {0} value that isn't a finite number")]
+    NotFinite(&'static str),
+    #[error("world file has an object at {0} which is outside the map")]
+    ObjectOffMap(IVec3),
+    #[error("world file has {0} players, it needs exactly one")]
+    Players(usize),
+    #[error("world file has unknown character model `{0}`")]
+    UnknownCharacter(String),
+    #[error("world file has an invalid string")]
+    Utf8(#[from] std::string::FromUtf8Error),
+    #[error("there is no terrain to save")]
+    NoTerrain,
+}
+
+/// Everything that makes up a world, independent of how it was generated
+struct WorldFile {
+    settings: TerrainSettings,
+    hight_map: Vec<f32>,
+    heat_map: Vec<f32>,
+    moisture_map: Vec<f32>,
+    /// names of the biomes used by `biome_map`
+    biomes: Vec<String>,
+    biome_map: Vec<u16>,
+    objects: Vec<(IVec3, Object)>,
+    characters: Vec<SavedCharacter>,
+}
+
+struct SavedCharacter {
+    player: bool,
+    file: String,
+    cell: IVec3,
+}
+
+impl WorldFile {
+    fn write(&self, out: &mut impl Write) -> Result<(), WorldFileError> {
+        out.write_all(MAGIC)?;
+        write_u32(out, VERSION)?;
+        let settings = &self.settings;
+        write_u32(out, settings.size)?;
+        write_u32(out, settings.seed)?;
+        write_u32(out, settings.octaves as u32)?;
+        for frequency in [
+            settings.hight_frequency,
+            settings.heat_frequency.x,
+            settings.heat_frequency.y,
+            settings.moisture_frequency,
+        ] {
+            out.write_all(&frequency.to_le_bytes())?;
+        }
+        out.write_all(&settings.hight_scale.to_le_bytes())?;
+        out.write_all(&settings.water_level.to_le_bytes())?;
+        for hight in &self.hight_map {
+            out.write_all(&hight.to_le_bytes())?;
+        }
+        for heat in &self.heat_map {
+            out.write_all(&heat.to_le_bytes())?;
+        }
+        for moisture in &self.moisture_map {
+            out.write_all(&moisture.to_le_bytes())?;
+        }
+        write_u32(out, self.biomes.len() as u32)?;
+        for name in &self.biomes {
+            write_str(out, name)?;
+        }
+        for biome in &self.biome_map {
+            out.write_all(&biome.to_le_bytes())?;
+        }
+        write_u32(out, self.objects.len() as u32)?;
+        for (tile, object) in &self.objects {
+            write_ivec3(out, *tile)?;
+            write_str(out, &object.rule)?;
+            for value in [object.scale, object.rotation, object.age] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        write_u32(out, self.characters.len() as u32)?;
+        for character in &self.characters {
+            out.write_all(&[character.player as u8])?;
+            write_str(out, &character.file)?;
+            write_ivec3(out, character.cell)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn read(input: &mut impl Read) -> Result<WorldFile, WorldFileError> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(WorldFileError::BadMagic);
+        }
+        let version = read_u32(input)?;
+        if version != VERSION {
+            return Err(WorldFileError::Version { found: version });
+        }
+        let settings = TerrainSettings {
+            size: read_u32(input)?,
+            seed: read_u32(input)?,
+            octaves: read_u32(input)? as usize,
+            hight_frequency: read_f64(input)?,
+            heat_frequency: DVec2::new(read_f64(input)?, read_f64(input)?),
+            moisture_frequency: read_f64(input)?,
+            hight_scale: read_f32(input)?,
+            water_level: read_f32(input)?,
+        };
+        settings.validate()?;
+        let volume = settings.size as usize * settings.size as usize;
+        let hight_map = read_map(input, "hight", volume)?;
+        let heat_map = read_map(input, "heat", volume)?;
+        let moisture_map = read_map(input, "moisture", volume)?;
+        let mut biomes = Vec::new();
+        // biome_map indices are u16
+        for _ in 0..read_count(input, "biomes", u16::MAX as u32 + 1)? {
+            biomes.push(read_str(input)?);
+        }
+        let mut biome_map = Vec::with_capacity(volume);
+        for _ in 0..volume {
+            let mut bytes = [0; 2];
+            input.read_exact(&mut bytes)?;
+            let biome = u16::from_le_bytes(bytes);
+            if biome as usize >= biomes.len() {
+                return Err(WorldFileError::BiomeIndex(biome));
+            }
+            biome_map.push(biome);
+        }
+        // the map is centred on the origin
+        let half = settings.size as i32 / 2;
+        let on_map = |tile: IVec3| {
+            let local = tile.xz() + half;
+            local.cmpge(IVec2::ZERO).all() && local.cmplt(IVec2::splat(settings.size as i32)).all()
+        };
+        let mut objects = Vec::new();
+        for _ in 0..read_count(input, "objects", volume as u32)? {
+            let tile = read_ivec3(input)?;
+            if !on_map(tile) {
+                return Err(WorldFileError::ObjectOffMap(tile));
+            }
+            objects.push((
+                tile,
+                Object {
+                    rule: read_str(input)?.into(),
+                    scale: read_f32(input)?,
+                    rotation: read_f32(input)?,
+                    age: read_f32(input)?,
+                },
+            ));
+        }
+        let mut characters = Vec::new();
+        for _ in 0..read_count(input, "characters", volume as u32)? {
+            let mut player = [0];
+            input.read_exact(&mut player)?;
+            characters.push(SavedCharacter {
+                player: player[0] != 0,
+                file: read_str(input)?,
+                cell: read_ivec3(input)?,
+            });
+        }
+        // the game needs exactly one character to control
+        let players = characters
+            .iter()
+            .filter(|character| character.player)
+            .count();
+        if players != 1 {
+            return Err(WorldFileError::Players(players));
+        }
+        Ok(WorldFile {
+            settings,
+            hight_map,
+            heat_map,
+            moisture_map,
+            biomes,
+            biome_map,
+            objects,
+            characters,
+        })
+    }
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> std::io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_str(out: &mut impl Write, value: &str) -> std::io::Result<()> {
+    write_u32(out, value.len() as u32)?;
+    out.write_all(value.as_bytes())
+}
+
+fn write_ivec3(out: &mut impl Write, value: IVec3) -> std::io::Result<()> {
+    for v in value.to_array() {
+        out.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u32(input: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(input: &mut impl Read) -> std::io::Result<f32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> std::io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+/// a length or count that is checked before anything is allocated for it
+/// `len` values of one of the maps, every one has to be finite
+fn read_map(
+    input: &mut impl Read,
+    what: &'static str,
+    len: usize,
+) -> Result<Vec<f32>, WorldFileError> {
+    let mut map = Vec::with_capacity(len);
+    for _ in 0..len {
+        let value = read_f32(input)?;
+        if !value.is_finite() {
+            return Err(WorldFileError::NotFinite(what));
+        }
+        map.push(value);
+    }
+    Ok(map)
+}
+
+fn read_count(input: &mut impl Read, what: &'static str, max: u32) -> Result<u32, WorldFileError> {
+    let count = read_u32(input)?;
+    if count > max {
+        return Err(WorldFileError::TooMany { what, count, max });
+    }
+    Ok(count)
+}
+
+fn read_str(input: &mut impl Read) -> Result<String, WorldFileError> {
+    let len = read_count(input, "bytes in a string", MAX_STRING)?;
+    let mut bytes = vec![0; len as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn read_ivec3(input: &mut impl Read) -> std::io::Result<IVec3> {
+    let mut out = [0; 3];
+    for v in &mut out {
+        let mut bytes = [0; 4];
+        input.read_exact(&mut bytes)?;
+        *v = i32::from_le_bytes(bytes);
+    }
+    Ok(IVec3::from_array(out))
+}
+
+/// the handles of saved biome names, out of the biomes of the rule set
+fn find_biomes(
+    names: &[String],
+    known: &[Handle<Biome>],
+    biomes: &Assets<Biome>,
+) -> Result<Vec<Handle<Biome>>, WorldFileError> {
+    let mut handles = Vec::with_capacity(names.len());
+    for name in names {
+        let handle = known
+            .iter()
+            .find(|handle| biomes.get(*handle).is_some_and(|b| b.name == *name))
+            .cloned();
+        match handle {
+            Some(handle) => handles.push(handle),
+            None if name == "Void" => handles.push(VOID),
+            None => return Err(WorldFileError::UnknownBiome(name.clone())),
+        }
+    }
+    Ok(handles)
+}
+
+fn save_world(
+    mut events: EventReader<SaveWorld>,
+    terrain: Query<(&Terrain, &Objects)>,
+    characters: Query<(&File, &PastCell, Has<Player>)>,
+    biomes: Res<Assets<Biome>>,
+) {
+    for SaveWorld(path) in events.read() {
+        let result = (|| {
+            let Ok((terrain, objects)) = terrain.get_single() else {
+                return Err(WorldFileError::NoTerrain);
+            };
+            let mut names = Vec::new();
+            let mut lookup = HashMap::new();
+            let mut biome_map = Vec::with_capacity(terrain.biome_map.len());
+            for handle in &terrain.biome_map {
+                let index = *lookup.entry(handle.id()).or_insert_with(|| {
+                    // biomes that aren't loaded are saved as Void
+                    let name = biomes
+                        .get(handle)
+                        .map(|biome| biome.name.to_string())
+                        .unwrap_or_else(|| "Void".into());
+                    names.push(name);
+                    names.len() as u16 - 1
+                });
+                biome_map.push(index);
+            }
+            let world = WorldFile {
+                settings: terrain.settings.clone(),
+                hight_map: terrain.hight_map.clone(),
+                heat_map: terrain.heat_map.clone(),
+                moisture_map: terrain.moisture_map.clone(),
+                biomes: names,
+                biome_map,
+                objects: objects
+                    .0
+                    .iter()
+                    .map(|(tile, object)| (*tile, object.clone()))
+                    .collect(),
+                characters: characters
+                    .iter()
+                    .map(|(file, past, player)| SavedCharacter {
+                        player,
+                        file: file.0.to_string(),
+                        cell: past.cell,
+                    })
+                    .collect(),
+            };
+            world.write(&mut BufWriter::new(std::fs::File::create(path)?))
+        })();
+        match result {
+            Ok(()) => info!("Saved world to {}", path.display()),
+            Err(e) => error!("Failed to save world to {}: {e}", path.display()),
+        }
+    }
+}
+
+fn load_world(
+    mut commands: Commands,
+    mut events: EventReader<LoadWorld>,
+    terrain: Query<Entity, With<Terrain>>,
+    characters: Query<Entity, With<File>>,
+    biomes: Res<Biomes>,
+    rule_sets: Res<Assets<BiomeRuleSet>>,
+    biome_assets: Res<Assets<Biome>>,
+    asset_server: Res<AssetServer>,
+    mut settings: ResMut<TerrainSettings>,
+    mut state: ResMut<NextState<TerrainState>>,
+) {
+    for LoadWorld(path) in events.read() {
+        let result = (|| {
+            let world = WorldFile::read(&mut BufReader::new(std::fs::File::open(path)?))?;
+            let known = rule_sets
+                .get(&biomes.0)
+                .map(|rules| rules.biomes())
+                .unwrap_or_default();
+            let handles = find_biomes(&world.biomes, known, &biome_assets)?;
+            let mut characters = Vec::with_capacity(world.characters.len());
+            for character in world.characters {
+                let Some(file) = CHARACTERS.iter().find(|file| **file == character.file) else {
+                    return Err(WorldFileError::UnknownCharacter(character.file));
+                };
+                characters.push((*file, character.cell, character.player));
+            }
+
+            let terrain = Terrain {
+                settings: world.settings,
+                biome_map: world
+                    .biome_map
+                    .iter()
+                    .map(|index| handles[*index as usize].clone())
+                    .collect(),
+                hight_map: world.hight_map,
+                heat_map: world.heat_map,
+                moisture_map: world.moisture_map,
+            };
+            let objects = Objects(world.objects.into_iter().collect());
+            Ok((terrain, objects, characters))
+        })();
+        let (new_terrain, objects, saved_characters) = match result {
+            Ok(world) => world,
+            Err(e) => {
+                error!("Failed to load world from {}: {e}", path.display());
+                continue;
+            }
+        };
+        for entity in &terrain {
+            commands.entity(entity).despawn_recursive();
+        }
+        // matches the loaded terrain so it isn't regenerated
+        *settings = new_terrain.settings.clone();
+        for entity in &characters {
+            commands.entity(entity).despawn_recursive();
+        }
+        // the resource only catches up with the new settings next frame
+        let seed = WorldSeed::from(&new_terrain.settings);
+        let mut npcs = 0..;
+        for (file, cell, player) in saved_characters {
+            let pos = new_terrain.tile_position(cell).unwrap_or(cell.as_vec3());
+            let mut entity = commands.spawn((
+                character(file, &asset_server),
+                Transform::from_translation(pos),
+                PastCell {
+                    cell,
+                    start_time: 0.,
+                },
+            ));
+            if player {
+                entity.insert(Player);
+            } else if let Some(index) = npcs.next() {
+                entity.insert(EntityRng(seed.entity_rng(Stream::Wander, index)));
+            }
+        }
+        commands.spawn((
+            Transform::default(),
+            Visibility::default(),
+            Name::new("Terrain"),
+            objects,
+            new_terrain,
+        ));
+        // wait for the chunks around the characters to be built
+        state.set(TerrainState::Loading);
+        info!("Loaded world from {}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::terrain::chunks::CHUNK_SIZE;
+
+    /// the names of every biome in the assets folder, and Void
+    fn biome_names() -> Vec<String> {
+        #[derive(Deserialize)]
+        struct Named {
+            name: String,
+        }
+        let mut names = std::fs::read_dir("assets/biomes")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".biome.ron"))
+            .map(|path| {
+                let file = std::fs::read_to_string(&path).unwrap();
+                ron::de::from_str::<Named>(&file).unwrap().name
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names.push("Void".into());
+        names
+    }
+
+    /// a small world with an object, the player and every biome
+    fn world(names: &[String]) -> WorldFile {
+        let settings = TerrainSettings {
+            size: CHUNK_SIZE as u32,
+            ..Default::default()
+        };
+        let volume = (settings.size * settings.size) as usize;
+        WorldFile {
+            settings,
+            hight_map: (0..volume).map(|i| i as f32 / volume as f32).collect(),
+            heat_map: vec![0.5; volume],
+            moisture_map: vec![0.25; volume],
+            biomes: names.to_vec(),
+            biome_map: (0..volume).map(|i| (i % names.len()) as u16).collect(),
+            objects: vec![(
+                IVec3::new(-3, 0, 7),
+                Object {
+                    rule: "palm".into(),
+                    scale: 1.2,
+                    rotation: 0.5,
+                    age: 3.,
+                },
+            )],
+            characters: vec![SavedCharacter {
+                player: true,
+                file: CHARACTERS[0].into(),
+                cell: IVec3::new(1, 0, -2),
+            }],
+        }
+    }
+
+    fn write_read(world: &WorldFile) -> Result<WorldFile, WorldFileError> {
+        let mut bytes = Vec::new();
+        world.write(&mut bytes).unwrap();
+        WorldFile::read(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn round_trip_every_biome() {
+        let names = biome_names();
+        let world = world(&names);
+        let read = write_read(&world).unwrap();
+
+        assert_eq!(read.settings, world.settings);
+        assert_eq!(read.hight_map, world.hight_map);
+        assert_eq!(read.heat_map, world.heat_map);
+        assert_eq!(read.moisture_map, world.moisture_map);
+        assert_eq!(read.biome_map, world.biome_map);
+        let (tile, object) = &read.objects[0];
+        assert_eq!(*tile, IVec3::new(-3, 0, 7));
+        assert_eq!(&*object.rule, "palm");
+        assert_eq!((object.scale, object.rotation, object.age), (1.2, 0.5, 3.));
+        assert!(read.characters[0].player);
+        assert_eq!(read.characters[0].file, CHARACTERS[0]);
+        assert_eq!(read.characters[0].cell, IVec3::new(1, 0, -2));
+
+        // every saved name, river included, has to be found again
+        let mut assets = Assets::<Biome>::default();
+        let known = names
+            .iter()
+            .filter(|name| *name != "Void")
+            .map(|name| {
+                assets.add(Biome {
+                    name: name.clone().into(),
+                    move_cost: 1.,
+                    color: Color::WHITE,
+                    texture: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let handles = find_biomes(&read.biomes, &known, &assets).unwrap();
+        for (name, handle) in names.iter().zip(&handles) {
+            let found = assets.get(handle).map_or("Void", |biome| &biome.name);
+            assert_eq!(found, name);
+        }
+    }
+
+    #[test]
+    fn rejects_files_the_game_cant_run() {
+        let names = biome_names();
+
+        let mut no_player = world(&names);
+        no_player.characters[0].player = false;
+        assert!(matches!(
+            write_read(&no_player),
+            Err(WorldFileError::Players(0))
+        ));
+
+        let mut two_players = world(&names);
+        two_players.characters.push(SavedCharacter {
+            player: true,
+            file: CHARACTERS[0].into(),
+            cell: IVec3::ZERO,
+        });
+        assert!(matches!(
+            write_read(&two_players),
+            Err(WorldFileError::Players(2))
+        ));
+
+        let mut off_map = world(&names);
+        let outside = IVec3::new(CHUNK_SIZE as i32, 0, 0);
+        off_map.objects[0].0 = outside;
+        assert!(matches!(
+            write_read(&off_map),
+            Err(WorldFileError::ObjectOffMap(tile)) if tile == outside
+        ));
+
+        let mut bad_hight = world(&names);
+        bad_hight.hight_map[5] = f32::NAN;
+        assert!(matches!(
+            write_read(&bad_hight),
+            Err(WorldFileError::NotFinite("hight"))
+        ));
+    }
+}
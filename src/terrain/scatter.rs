@@ -0,0 +1,355 @@
+use std::sync::Arc;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    gltf::GltfNode,
+    prelude::*,
+    utils::HashMap,
+};
+use rand::Rng;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    path_finding::Passability,
+    world_seed::{Stream, WorldSeed},
+};
+
+use super::{objects::Objects, Biome, Terrain};
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<ScatterSet>()
+        .register_asset_loader(ScatterSetLoader)
+        .init_resource::<Scatter>();
+}
+
+/// Places one kind of object on the tiles of some biomes
+#[derive(Clone)]
+pub struct ScatterRule {
+    pub name: Arc<str>,
+    pub prefab: Handle<Scene>,
+    /// glTF node whose mesh draws the object away from the camera
+    pub instance: Option<Handle<GltfNode>>,
+    /// the full prefab is drawn closer to the camera than this
+    pub scene_distance: f32,
+    /// not drawn at all further than this
+    pub view_distance: f32,
+    pub biomes: Vec<Handle<Biome>>,
+    /// chance of an object on each tile of the biomes
+    pub density: f64,
+    /// no other object is placed closer than this many tiles
+    pub spacing: f32,
+    /// full grown size
+    pub scale: (f32, f32),
+    /// turn around the up axis in radians
+    pub rotation: (f32, f32),
+    /// seconds to grow to full size, 0 for objects that don't grow
+    pub grow_time: f32,
+    pub choppable: bool,
+    /// `(x, z)` offsets of the tiles the object stands on from the tile it is placed on
+    pub footprint: Vec<IVec2>,
+    pub passability: Passability,
+}
+
+/// The rules used to place objects on new terrain, loaded from a `.scatter.ron` file
+#[derive(Asset, TypePath, Clone)]
+pub struct ScatterSet {
+    pub rules: Vec<ScatterRule>,
+    #[dependency]
+    prefabs: Vec<Handle<Scene>>,
+    #[dependency]
+    instances: Vec<Handle<GltfNode>>,
+    #[dependency]
+    biomes: Vec<Handle<Biome>>,
+}
+
+impl ScatterRule {
+    /// the tiles an object placed on `tile` stands on
+    pub fn footprint_tiles(&self, tile: IVec3) -> impl Iterator<Item = IVec3> + '_ {
+        self.footprint
+            .iter()
+            .map(move |offset| tile + IVec3::new(offset.x, 0, offset.y))
+    }
+}
+
+impl ScatterSet {
+    /// the rule with a name, the loader makes sure there is only one
+    pub fn rule(&self, name: &str) -> Option<&ScatterRule> {
+        self.rules.iter().find(|rule| &*rule.name == name)
+    }
+}
+
+/// the scatter rules every new [`Terrain`] is generated with
+#[derive(Resource)]
+pub struct Scatter(pub Handle<ScatterSet>);
+
+impl FromWorld for Scatter {
+    fn from_world(world: &mut World) -> Self {
+        Scatter(
+            world
+                .resource::<AssetServer>()
+                .load("objects/default.scatter.ron"),
+        )
+    }
+}
+
+impl Objects {
+    /// rolls the rules for every tile, the first rule that places an object on a tile wins
+    pub(super) fn generate(terrain: &Terrain, seed: WorldSeed, set: &ScatterSet) -> Objects {
+        // how far to look for objects that are too close
+        let reach = set
+            .rules
+            .iter()
+            .map(|rule| rule.spacing.ceil() as i32)
+            .max()
+            .unwrap_or_default();
+        let mut objects = HashMap::new();
+        let mut spacing = HashMap::new();
+        for (index, biome) in terrain.biome_map.iter().enumerate() {
+            let tile = terrain.index_tile(index);
+            let mut rng = None;
+            for rule in &set.rules {
+                if !rule.biomes.contains(biome) {
+                    continue;
+                }
+                let rng = rng.get_or_insert_with(|| seed.tile_rng(Stream::Objects, tile));
+                if !rng.gen_bool(rule.density) {
+                    continue;
+                }
+                // the bigger spacing of the two objects applies
+                let crowded = (-reach..=reach).any(|z| {
+                    (-reach..=reach).any(|x| {
+                        let other = tile + IVec3::new(x, 0, z);
+                        spacing.get(&other).is_some_and(|other_spacing: &f32| {
+                            let distance = other.as_vec3().distance(tile.as_vec3());
+                            distance < rule.spacing.max(*other_spacing)
+                        })
+                    })
+                });
+                if crowded {
+                    continue;
+                }
+                objects.insert(
+                    tile,
+                    super::objects::Object {
+                        rule: rule.name.clone(),
+                        scale: rng.gen_range(rule.scale.0..=rule.scale.1),
+                        rotation: rng.gen_range(rule.rotation.0..=rule.rotation.1),
+                        age: 0.,
+                    },
+                );
+                spacing.insert(tile, rule.spacing);
+                break;
+            }
+        }
+        Objects(objects)
+    }
+}
+
+/// `.scatter.ron` file layout
+#[derive(Deserialize)]
+struct ScatterSetFile {
+    objects: Vec<ScatterRuleFile>,
+}
+
+#[derive(Deserialize)]
+struct ScatterRuleFile {
+    name: String,
+    /// asset path of the scene to spawn
+    prefab: String,
+    /// asset path of a glTF node like `tree.glb#Node0`
+    #[serde(default)]
+    instance: Option<String>,
+    #[serde(default)]
+    scene_distance: f32,
+    #[serde(default = "default_view_distance")]
+    view_distance: f32,
+    /// asset paths of the `.biome.ron` files the object is placed on
+    biomes: Vec<String>,
+    density: f64,
+    #[serde(default)]
+    spacing: f32,
+    #[serde(default = "full_size")]
+    scale: (f32, f32),
+    /// in degrees
+    #[serde(default = "any_rotation")]
+    rotation: (f32, f32),
+    #[serde(default)]
+    grow_time: f32,
+    #[serde(default)]
+    choppable: bool,
+    #[serde(default = "one_tile")]
+    footprint: Vec<(i32, i32)>,
+    #[serde(default)]
+    passability: PassabilityFile,
+}
+
+#[derive(Deserialize, Default)]
+enum PassabilityFile {
+    #[default]
+    Walkable,
+    /// how many times the tile costs to cross
+    Slow(f32),
+    Blocked,
+}
+
+fn default_view_distance() -> f32 {
+    120.
+}
+
+fn one_tile() -> Vec<(i32, i32)> {
+    vec![(0, 0)]
+}
+
+fn full_size() -> (f32, f32) {
+    (1., 1.)
+}
+
+fn any_rotation() -> (f32, f32) {
+    (0., 360.)
+}
+
+#[derive(Debug, Error)]
+pub enum ScatterSetLoadError {
+    #[error("could not read scatter file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse scatter file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("object {0} has an empty name")]
+    NoName(usize),
+    #[error("more than one object is named `{0}`")]
+    DuplicateName(String),
+    #[error("object `{name}` has an invalid {field} {value}")]
+    Invalid {
+        name: String,
+        field: &'static str,
+        value: String,
+    },
+}
+
+#[derive(Default)]
+struct ScatterSetLoader;
+
+impl AssetLoader for ScatterSetLoader {
+    type Asset = ScatterSet;
+    type Settings = ();
+    type Error = ScatterSetLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<ScatterSet, ScatterSetLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: ScatterSetFile = ron::de::from_bytes(&bytes)?;
+        let mut rules = Vec::with_capacity(file.objects.len());
+        let mut prefabs = Vec::new();
+        let mut instances = Vec::new();
+        let mut biomes = Vec::new();
+        for (index, object) in file.objects.into_iter().enumerate() {
+            if object.name.is_empty() {
+                return Err(ScatterSetLoadError::NoName(index));
+            }
+            // placed objects only keep the name of their rule
+            if rules
+                .iter()
+                .any(|rule: &ScatterRule| *rule.name == object.name)
+            {
+                return Err(ScatterSetLoadError::DuplicateName(object.name));
+            }
+            let invalid = |field, value: String| ScatterSetLoadError::Invalid {
+                name: object.name.clone(),
+                field,
+                value,
+            };
+            if !(0. ..=1.).contains(&object.density) {
+                return Err(invalid("density", object.density.to_string()));
+            }
+            if !object.spacing.is_finite() || object.spacing < 0. {
+                return Err(invalid("spacing", object.spacing.to_string()));
+            }
+            // gen_range panics on bounds that aren't finite
+            let (min, max) = object.scale;
+            if !min.is_finite() || !max.is_finite() || min <= 0. || min > max {
+                return Err(invalid("scale", format!("{min}..{max}")));
+            }
+            let (min, max) = object.rotation;
+            if !min.is_finite() || !max.is_finite() || min > max {
+                return Err(invalid("rotation", format!("{min}..{max}")));
+            }
+            if object.grow_time.is_nan() || object.grow_time < 0. {
+                return Err(invalid("grow_time", object.grow_time.to_string()));
+            }
+            if object.footprint.is_empty() {
+                return Err(invalid("footprint", "[]".into()));
+            }
+            let passability = match object.passability {
+                PassabilityFile::Walkable => Passability::Walkable,
+                PassabilityFile::Slow(factor) => {
+                    if factor.is_nan() || factor < 1. {
+                        return Err(invalid("passability", format!("Slow({factor})")));
+                    }
+                    Passability::Slow(factor)
+                }
+                PassabilityFile::Blocked => Passability::Blocked,
+            };
+            if object.scene_distance.is_nan() || object.scene_distance < 0. {
+                return Err(invalid("scene_distance", object.scene_distance.to_string()));
+            }
+            if object.view_distance.is_nan() || object.view_distance < object.scene_distance {
+                return Err(invalid("view_distance", object.view_distance.to_string()));
+            }
+            let prefab: Handle<Scene> = load_context.load(object.prefab);
+            if !prefabs.contains(&prefab) {
+                prefabs.push(prefab.clone());
+            }
+            let instance = object
+                .instance
+                .map(|instance| load_context.load::<GltfNode>(instance));
+            if let Some(instance) = &instance {
+                if !instances.contains(instance) {
+                    instances.push(instance.clone());
+                }
+            }
+            // a missing or broken biome file fails this scatter set through its dependencies
+            let object_biomes = object
+                .biomes
+                .into_iter()
+                .map(|biome| load_context.load(biome))
+                .collect::<Vec<Handle<Biome>>>();
+            for biome in &object_biomes {
+                if !biomes.contains(biome) {
+                    biomes.push(biome.clone());
+                }
+            }
+            rules.push(ScatterRule {
+                name: object.name.into(),
+                prefab,
+                instance,
+                scene_distance: object.scene_distance,
+                view_distance: object.view_distance,
+                biomes: object_biomes,
+                density: object.density,
+                spacing: object.spacing,
+                scale: object.scale,
+                rotation: (min.to_radians(), max.to_radians()),
+                grow_time: object.grow_time,
+                choppable: object.choppable,
+                footprint: object.footprint.into_iter().map(IVec2::from).collect(),
+                passability,
+            });
+        }
+        Ok(ScatterSet {
+            rules,
+            prefabs,
+            instances,
+            biomes,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scatter.ron"]
+    }
+}
@@ -0,0 +1,107 @@
+use bevy::{input::common_conditions::input_just_pressed, math::DVec2, prelude::*};
+use thiserror::Error;
+
+use super::{chunks::CHUNK_SIZE, Terrain};
+
+/// hight of the shore in the default biome rules, where sand starts
+const SHORE: f32 = 0.1;
+/// biggest map that fits in memory, a multiple of the chunk size
+const MAX_SIZE: u32 = 4000;
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<TerrainSettings>()
+        .init_resource::<TerrainSettings>()
+        .add_systems(
+            Update,
+            (
+                next_seed.run_if(input_just_pressed(KeyCode::F6)),
+                regenerate_terrain,
+            )
+                .chain(),
+        );
+}
+
+/// How new terrain is generated, changing this replaces the current terrain with a new one
+#[derive(Resource, Reflect, Clone, PartialEq, Debug)]
+#[reflect(Resource)]
+pub struct TerrainSettings {
+    /// tiles along each side of the map, must be a multiple of the chunk size
+    pub size: u32,
+    pub seed: u32,
+    /// noise layers added together, more gives rougher terrain
+    pub octaves: usize,
+    pub hight_frequency: f64,
+    /// separate x and z frequencies so climate changes faster one way than the other
+    pub heat_frequency: DVec2,
+    pub moisture_frequency: f64,
+    /// world units between the lowest and highest point
+    pub hight_scale: f32,
+    /// raising it floods more of the map, 0.1 matches the shore of the default biome rules
+    pub water_level: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            size: 1000,
+            seed: 0,
+            octaves: noise::Fbm::<noise::OpenSimplex>::DEFAULT_OCTAVE_COUNT,
+            hight_frequency: 0.026,
+            heat_frequency: DVec2::new(0.0036, 0.0016),
+            moisture_frequency: 0.008,
+            hight_scale: 10.,
+            water_level: SHORE,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TerrainSettingsError {
+    #[error("map size {0} is not a multiple of the chunk size {CHUNK_SIZE} up to {MAX_SIZE}")]
+    Size(u32),
+    #[error("octaves must be between 1 and {}, got {0}", noise::Fbm::<noise::OpenSimplex>::MAX_OCTAVES)]
+    Octaves(usize),
+    #[error("hight scale must be positive, got {0}")]
+    HightScale(f32),
+}
+
+impl TerrainSettings {
+    pub fn validate(&self) -> Result<(), TerrainSettingsError> {
+        if self.size == 0 || self.size > MAX_SIZE || self.size as isize % CHUNK_SIZE != 0 {
+            return Err(TerrainSettingsError::Size(self.size));
+        }
+        if !(1..=noise::Fbm::<noise::OpenSimplex>::MAX_OCTAVES).contains(&self.octaves) {
+            return Err(TerrainSettingsError::Octaves(self.octaves));
+        }
+        if self.hight_scale.is_nan() || self.hight_scale <= 0. {
+            return Err(TerrainSettingsError::HightScale(self.hight_scale));
+        }
+        Ok(())
+    }
+
+    /// how far the land is lowered so `water_level` lines up with the shore
+    pub(super) fn sea_offset(&self) -> f32 {
+        SHORE - self.water_level
+    }
+}
+
+fn next_seed(mut settings: ResMut<TerrainSettings>) {
+    settings.seed = settings.seed.wrapping_add(1);
+    info!("Generating terrain with seed {}", settings.seed);
+}
+
+/// despawns terrain made with old settings, `spawn_terrain` then makes a new one
+fn regenerate_terrain(
+    mut commands: Commands,
+    settings: Res<TerrainSettings>,
+    terrains: Query<(Entity, &Terrain)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (entity, terrain) in &terrains {
+        if terrain.settings != *settings {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
+
+use crate::{path_finding::MoveCost, ui::ContextActions, Cell, PastCell, Player, Root};
+
+use super::{
+    planes::{Plane, Transition, TransitionKind},
+    Terrain, TerrainContext,
+};
+
+/// tiles along each side of the watchtower platform
+const TOWER_SIZE: IVec2 = IVec2::new(4, 4);
+/// how far the platform is above the highest ground under it
+const TOWER_HIGHT: f32 = 3.;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        spawn_watchtower.run_if(input_just_pressed(KeyCode::F7)),
+    );
+}
+
+/// A test lookout platform on plane 1 with a ladder up from where the player stands,
+/// for trying out planes. It is debug only and not saved with the world.
+fn spawn_watchtower(
+    mut commands: Commands,
+    terrains: Query<(Entity, &Terrain)>,
+    player: Query<&PastCell, With<Player>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    context: Res<TerrainContext>,
+) {
+    let (Ok((terrain_entity, terrain)), Ok(past)) = (terrains.get_single(), player.get_single())
+    else {
+        return;
+    };
+    if past.cell.y != 0 {
+        warn!("the watchtower can only be built from the ground");
+        return;
+    }
+    let ladder_foot = past.cell;
+    let tower_min = IVec2::new(ladder_foot.x + 1, ladder_foot.z - (TOWER_SIZE.y - 1) / 2);
+    let tower_max = tower_min + TOWER_SIZE - IVec2::ONE;
+    let ladder_top = IVec3::new(tower_min.x, 1, ladder_foot.z);
+    let Some(foot_hight) = terrain.tile_hight(ladder_foot) else {
+        return;
+    };
+    let mut ground = foot_hight;
+    for z in tower_min.y..=tower_max.y {
+        for x in tower_min.x..=tower_max.x {
+            let Some(hight) = terrain.tile_hight(IVec3::new(x, 0, z)) else {
+                continue;
+            };
+            ground = ground.max(hight);
+        }
+    }
+    let floor = ground + TOWER_HIGHT;
+    let size = TOWER_SIZE.as_vec2();
+    let centre = (tower_min.as_vec2() + tower_max.as_vec2()) / 2.;
+    let wood = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.45, 0.3, 0.15),
+        perceptual_roughness: 0.9,
+        ..Default::default()
+    });
+
+    commands
+        .spawn((
+            Name::new("Watchtower"),
+            Transform::default(),
+            Visibility::default(),
+        ))
+        .with_children(|p| {
+            p.spawn((
+                Name::new("Watchtower"),
+                Root,
+                Plane(1),
+                Mesh3d(meshes.add(Cuboid::new(size.x, 0.2, size.y))),
+                MeshMaterial3d(wood.clone()),
+                Transform::from_xyz(centre.x, floor - 0.1, centre.y),
+                ContextActions {
+                    on_open: Some(context.on_open),
+                    options: vec![("Walk".into(), context.walk)],
+                    on_close: None,
+                },
+            ));
+            for z in tower_min.y..=tower_max.y {
+                for x in tower_min.x..=tower_max.x {
+                    p.spawn((
+                        Cell,
+                        Plane(1),
+                        MoveCost::default(),
+                        Transform::from_xyz(x as f32, floor, z as f32),
+                    ));
+                }
+            }
+            let leg = meshes.add(Cuboid::new(0.3, TOWER_HIGHT * 2., 0.3));
+            for (x, z) in [
+                (tower_min.x, tower_min.y),
+                (tower_min.x, tower_max.y),
+                (tower_max.x, tower_min.y),
+                (tower_max.x, tower_max.y),
+            ] {
+                // long enough to reach the lowest ground under the platform
+                p.spawn((
+                    Mesh3d(leg.clone()),
+                    MeshMaterial3d(wood.clone()),
+                    Transform::from_xyz(x as f32, floor - TOWER_HIGHT, z as f32),
+                ));
+            }
+            p.spawn((
+                Name::new("Ladder"),
+                Transition {
+                    a: ladder_foot,
+                    b: ladder_top,
+                    kind: TransitionKind::Ladder,
+                },
+                Mesh3d(meshes.add(Cuboid::new(0.1, floor - foot_hight, 0.8))),
+                MeshMaterial3d(wood),
+                Transform::from_xyz(
+                    tower_min.x as f32 - 0.5,
+                    (floor + foot_hight) / 2.,
+                    ladder_foot.z as f32,
+                ),
+            ));
+        })
+        .set_parent(terrain_entity);
+}
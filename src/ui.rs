@@ -2,17 +2,114 @@ use std::process::CommandArgs;
 
 use bevy::{ecs::system::SystemId, prelude::*, window::PrimaryWindow};
 
-use crate::Root;
+use crate::{
+    regions::{InRegion, RegionMap},
+    Player, Root,
+};
 
 #[derive(Component)]
 #[require(ContextActions)]
 pub struct ContextMenuRoot;
 
+/// seconds a message stays on screen
+const MESSAGE_TIME: f32 = 4.;
+
 pub fn plugin(app: &mut App) {
-    app.add_systems(Startup, spawn_right_click_menu)
-        .add_systems(Update, (update_right_click, context_buttons))
-        .add_systems(Last, run_context_action)
-        .add_event::<ContextEvent>();
+    app.add_systems(
+        Startup,
+        (
+            spawn_right_click_menu,
+            spawn_message_text,
+            spawn_region_text,
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            update_right_click,
+            context_buttons,
+            show_messages,
+            update_region_text,
+        ),
+    )
+    .add_systems(Last, run_context_action)
+    .add_event::<ContextEvent>()
+    .add_event::<GameMessage>();
+}
+
+/// A line of text for the player like "I can't reach that!"
+#[derive(Event, Clone, Debug)]
+pub struct GameMessage(pub String);
+
+#[derive(Component)]
+struct MessageText;
+
+fn spawn_message_text(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.),
+            left: Val::Px(10.),
+            ..Default::default()
+        },
+        MessageText,
+    ));
+}
+
+/// shows the newest message until it gets old
+fn show_messages(
+    mut messages: EventReader<GameMessage>,
+    mut text: Query<&mut Text, With<MessageText>>,
+    time: Res<Time>,
+    mut shown_at: Local<f32>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    if let Some(message) = messages.read().last() {
+        text.0.clone_from(&message.0);
+        *shown_at = time.elapsed_secs();
+    } else if !text.0.is_empty() && time.elapsed_secs() - *shown_at > MESSAGE_TIME {
+        text.0.clear();
+    }
+}
+
+#[derive(Component)]
+struct RegionText;
+
+fn spawn_region_text(mut commands: Commands) {
+    commands.spawn((
+        Text::default(),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..Default::default()
+        },
+        RegionText,
+    ));
+}
+
+/// shows the region the player is in
+fn update_region_text(
+    player: Query<Ref<InRegion>, With<Player>>,
+    regions: RegionMap,
+    mut text: Query<&mut Text, With<RegionText>>,
+) {
+    let (Ok(in_region), Ok(mut text)) = (player.get_single(), text.get_single_mut()) else {
+        return;
+    };
+    if !in_region.is_changed() {
+        return;
+    }
+    text.0 = match &in_region.0 {
+        Some(name) if regions.named(name).is_some_and(|region| region.pvp) => {
+            format!("{name} (PvP)")
+        }
+        Some(name) => name.clone(),
+        None => String::new(),
+    };
 }
 
 fn spawn_right_click_menu(mut commands: Commands) {
@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::terrain::TerrainSettings;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<WorldSeed>().add_systems(
+        PreUpdate,
+        sync_world_seed.run_if(resource_changed::<TerrainSettings>),
+    );
+}
+
+/// What random numbers are used for, each gets its own stream
+/// so rolling more in one doesn't change the others.
+/// The values seed the streams, new ones get a new value and old ones never change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Erosion = 0,
+    Rivers = 1,
+    Objects = 2,
+    Characters = 3,
+    Wander = 4,
+}
+
+/// The seed every random thing in the world comes from, it is the terrain settings seed.
+/// The same seed always hands out the same random numbers.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldSeed(pub u32);
+
+impl FromWorld for WorldSeed {
+    fn from_world(world: &mut World) -> Self {
+        world
+            .get_resource::<TerrainSettings>()
+            .map_or(WorldSeed(0), WorldSeed::from)
+    }
+}
+
+/// the only place the seed is taken from the settings, terrain made in the background
+/// uses the settings it was started with since the resource only follows them next frame
+impl From<&TerrainSettings> for WorldSeed {
+    fn from(settings: &TerrainSettings) -> Self {
+        WorldSeed(settings.seed)
+    }
+}
+
+impl WorldSeed {
+    /// random numbers for a whole subsystem
+    pub fn rng(self, stream: Stream) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.hash(stream, &[]))
+    }
+
+    /// random numbers for one tile, the same whatever order tiles are rolled in
+    pub fn tile_rng(self, stream: Stream, tile: IVec3) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.hash(stream, &[tile.x as u64, tile.y as u64, tile.z as u64]))
+    }
+
+    /// random numbers for one entity, `index` has to be stable between runs
+    /// like the order entities are spawned in
+    pub fn entity_rng(self, stream: Stream, index: u64) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.hash(stream, &[index]))
+    }
+
+    fn hash(self, stream: Stream, values: &[u64]) -> u64 {
+        let mut hash = mix(mix(self.0 as u64) ^ stream as u64);
+        for value in values {
+            hash = mix(hash ^ value);
+        }
+        hash
+    }
+}
+
+/// splitmix64, nearby inputs give unrelated outputs so neighbouring tiles don't roll alike
+fn mix(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Random numbers that belong to one entity so what it does doesn't depend on the rest of the world
+#[derive(Component)]
+pub struct EntityRng(pub ChaCha8Rng);
+
+fn sync_world_seed(settings: Res<TerrainSettings>, mut seed: ResMut<WorldSeed>) {
+    seed.set_if_neq(WorldSeed::from(&*settings));
+}